@@ -0,0 +1,287 @@
+//! All advisory sources behind one query: the rustsec database plus local directories of
+//! advisories in the same TOML/Markdown format, for in-house or embargoed findings, and OSV
+//! records for issues that haven't made it into RustSec.
+
+use crate::{osv, AdvisoryMeta, DepInfo, RUSTSEC_REPO};
+use anyhow::{Context, Result};
+use rustsec::database::{Database, Query};
+use rustsec::package::Version;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+pub struct Advisories {
+    rustsec: Database,
+    // With the directory they were loaded from
+    local: Vec<(String, rustsec::Advisory)>,
+    osv: Vec<osv::Osv>,
+    // Ids and aliases of all rustsec and local advisories, to leave out OSV copies of them
+    known: HashSet<String>,
+}
+
+/// An advisory affecting a crate version, with its source
+pub enum Found<'a> {
+    RustSec(&'a str, &'a rustsec::Advisory),
+    Osv(&'a str, &'a osv::Record),
+}
+
+impl Found<'_> {
+    pub fn id(&self) -> &str {
+        match self {
+            Found::RustSec(_, a) => a.metadata.id.as_str(),
+            Found::Osv(_, r) => &r.id,
+        }
+    }
+
+    /// Day of publication, YYYY-MM-DD
+    pub fn date(&self) -> Option<String> {
+        match self {
+            Found::RustSec(_, a) => Some(a.metadata.date.as_str().to_string()),
+            Found::Osv(_, r) => r.date(),
+        }
+    }
+
+    /// As opposed to an informational notice, see AdvisoryMeta
+    pub fn is_vulnerability(&self) -> bool {
+        match self {
+            Found::RustSec(_, a) => a.metadata.informational.is_none(),
+            Found::Osv(_, _) => true,
+        }
+    }
+
+    pub fn meta(&self) -> AdvisoryMeta {
+        match self {
+            Found::RustSec(source, a) => AdvisoryMeta::from_advisory(source, a),
+            Found::Osv(source, r) => r.meta(source),
+        }
+    }
+}
+
+impl Advisories {
+    pub fn new(rustsec: Database) -> Self {
+        let mut known = HashSet::new();
+        rustsec.iter().for_each(|a| add_known(&mut known, a));
+        Advisories {
+            rustsec,
+            local: vec![],
+            osv: vec![],
+            known,
+        }
+    }
+
+    /// Load all advisories below dir, .md files with TOML front matter as in the advisory-db.
+    /// Local advisories replace rustsec ones with the same id.
+    pub fn add_local(&mut self, dir: &Path) -> Result<()> {
+        let source = dir.to_string_lossy().to_string();
+        let mut files = vec![];
+        find_files(dir, &mut files).context(format!("List advisories in {}", source))?;
+        let before = self.local.len();
+        for file in files {
+            match load_local(dir, &file) {
+                Ok(a) => {
+                    add_known(&mut self.known, &a);
+                    self.local.push((source.clone(), a));
+                }
+                Err(e) => log::warn!("Skipping {}: {}", file.display(), e),
+            }
+        }
+        anyhow::ensure!(
+            self.local.len() > before,
+            "No advisories found in {}",
+            source
+        );
+        log::info!(
+            "{} local advisories from {}",
+            self.local.len() - before,
+            source
+        );
+        Ok(())
+    }
+
+    /// Load OSV records from a directory or zip file. Records already known by id or alias from
+    /// another source are left out of query results, even if their ranges differ.
+    pub fn add_osv(&mut self, path: &Path) -> Result<()> {
+        let osv = osv::Osv::load(path).context(format!("Load OSV from {}", path.display()))?;
+        self.osv.push(osv);
+        Ok(())
+    }
+
+    /// Advisories affecting a crate version, informational notices included
+    pub fn query(&self, dep: &DepInfo) -> Vec<Found> {
+        // Not Query::crate_scope(), which leaves out the notices
+        let query = Query::new()
+            .collection(rustsec::Collection::Crates)
+            .package_version(dep.name.clone(), dep.ver.clone())
+            .yanked(false);
+        let local = self
+            .local
+            .iter()
+            .filter(|(_, a)| query.matches(a))
+            .collect::<Vec<_>>();
+        let mut ret = self
+            .rustsec
+            .query(&query)
+            .into_iter()
+            .filter(|a| !local.iter().any(|(_, l)| l.metadata.id == a.metadata.id))
+            .map(|a| Found::RustSec(RUSTSEC_REPO, a))
+            .collect::<Vec<_>>();
+        ret.extend(
+            local
+                .into_iter()
+                .map(|(source, a)| Found::RustSec(source, a)),
+        );
+
+        let mut seen = HashSet::new();
+        for osv in &self.osv {
+            for record in osv.query(dep) {
+                let copy = |known: &HashSet<String>| {
+                    known.contains(&record.id) || record.aliases.iter().any(|a| known.contains(a))
+                };
+                if copy(&self.known) || copy(&seen) {
+                    continue;
+                }
+                seen.insert(record.id.clone());
+                ret.push(Found::Osv(&osv.source, record));
+            }
+        }
+        ret
+    }
+
+    /// Advisories against the Rust toolchain and standard library, rather than a crate
+    pub fn toolchain(&self, ver: &Version) -> Vec<Found> {
+        let affects = |a: &rustsec::Advisory| {
+            a.metadata.collection == Some(rustsec::Collection::Rust)
+                && !a.metadata.yanked
+                && a.versions.is_vulnerable(ver)
+        };
+        let local = self
+            .local
+            .iter()
+            .filter(|(_, a)| affects(a))
+            .collect::<Vec<_>>();
+        let mut ret = self
+            .rustsec
+            .iter()
+            .filter(|a| affects(a))
+            .filter(|a| !local.iter().any(|(_, l)| l.metadata.id == a.metadata.id))
+            .map(|a| Found::RustSec(RUSTSEC_REPO, a))
+            .collect::<Vec<_>>();
+        ret.extend(
+            local
+                .into_iter()
+                .map(|(source, a)| Found::RustSec(source, a)),
+        );
+        ret
+    }
+}
+
+fn add_known(known: &mut HashSet<String>, a: &rustsec::Advisory) {
+    known.insert(a.metadata.id.as_str().to_string());
+    known.extend(a.metadata.aliases.iter().map(|id| id.as_str().to_string()));
+}
+
+/// The rustsec crate only sets the collection when loading its own repository, so take it from
+/// the directory: advisories below rust/ are against the toolchain, all others against crates.
+fn load_local(dir: &Path, file: &Path) -> Result<rustsec::Advisory> {
+    let mut advisory = rustsec::Advisory::load_file(file)?;
+    if advisory.metadata.collection.is_none() {
+        let toolchain = file
+            .strip_prefix(dir)
+            .ok()
+            .and_then(|p| p.components().next())
+            .map_or(false, |c| c.as_os_str() == "rust");
+        advisory.metadata.collection = Some(match toolchain {
+            true => rustsec::Collection::Rust,
+            false => rustsec::Collection::Crates,
+        });
+    }
+    Ok(advisory)
+}
+
+fn find_files(dir: &Path, ret: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .map_or(true, |n| n.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            find_files(&path, ret)?;
+        } else if path.extension().map_or(false, |e| e == "md") {
+            ret.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::dep;
+    use std::str::FromStr;
+
+    fn fixture(dir: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests")
+            .join(dir)
+    }
+
+    fn ids(found: Vec<Found>) -> Vec<String> {
+        found.iter().map(|f| f.id().to_string()).collect()
+    }
+
+    #[test]
+    fn local() {
+        let mut db = Advisories::new(Database::open(&fixture("advisory-db")).unwrap());
+        db.add_local(&fixture("local-advisories")).unwrap();
+        assert_eq!(
+            vec!["RUSTSEC-2099-0002"],
+            ids(db.query(&dep("bar", "0.3.0")))
+        );
+        assert!(db.query(&dep("bar", "0.3.1")).is_empty());
+        assert_eq!(
+            vec!["RUSTSEC-2099-0001"],
+            ids(db.query(&dep("foo", "1.1.0")))
+        );
+    }
+
+    #[test]
+    fn toolchain() {
+        let mut db = Advisories::new(Database::open(&fixture("advisory-db")).unwrap());
+        db.add_local(&fixture("local-advisories")).unwrap();
+        let v = |s: &str| Version::from_str(s).unwrap();
+        assert_eq!(vec!["RUSTSEC-2099-0003"], ids(db.toolchain(&v("1.51.0"))));
+        // RUSTSEC-2099-0006 is withdrawn
+        assert!(db.toolchain(&v("1.52.0")).is_empty());
+        // Not a crate
+        assert!(db.query(&dep("std", "1.51.0")).is_empty());
+    }
+
+    #[test]
+    fn notices() {
+        let db = Advisories::new(Database::open(&fixture("advisory-db")).unwrap());
+        let mut found = ids(db.query(&dep("qux", "0.3.0")));
+        found.sort();
+        assert_eq!(vec!["RUSTSEC-2099-0004", "RUSTSEC-2099-0005"], found);
+        let found = db.query(&dep("qux", "0.4.0"));
+        assert_eq!(1, found.len());
+        assert!(!found[0].is_vulnerability());
+    }
+
+    #[test]
+    fn osv_copies() {
+        let mut db = Advisories::new(Database::open(&fixture("advisory-db")).unwrap());
+        db.add_osv(&fixture("osv")).unwrap();
+        // Only the GHSA range covers 0.6, but it's the same advisory
+        assert!(db.query(&dep("foo", "0.6.0")).is_empty());
+        assert_eq!(
+            vec!["RUSTSEC-2099-0001"],
+            ids(db.query(&dep("foo", "1.1.0")))
+        );
+        assert_eq!(
+            vec!["GHSA-dddd-eeee-ffff"],
+            ids(db.query(&dep("baz", "1.0.0")))
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Only the news: advisories published and versions yanked between two runs, and the ebuilds they
+//! hit. Found by diffing the rustsec and crates.io commits recorded in the runs' provenance.
+
+use crate::diff::pick_runs;
+use crate::report::read_output;
+use crate::{
+    gitrepo, parse_index_file, AdvisoryMeta, DepInfo, Ebuild, Output, YankingStatus,
+    CRATES_IO_REPO, OPTS, RUSTSEC_REPO,
+};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Clap, Debug)]
+pub struct AlertOpts {
+    /// Previous analysis result, defaults to the second to last run stored in the work dir
+    old: Option<PathBuf>,
+    /// Current analysis result, defaults to the last run stored in the work dir
+    new: Option<PathBuf>,
+    /// Write the alerts as JSON instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Alert {
+    #[serde(rename = "crate")]
+    id: DepInfo,
+    new_advisories: Vec<AdvisoryMeta>,
+    newly_yanked: bool,
+    ebuilds: Vec<Ebuild>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Alerts {
+    // All advisories added to the database, whether they hit anything or not
+    published: Vec<String>,
+    alerts: Vec<Alert>,
+}
+
+fn commit_of(output: &Output, repo: &str, path: &Path) -> Result<gitrepo::Rev> {
+    output
+        .provenance
+        .as_ref()
+        .and_then(|p| p.repositories.get(repo))
+        .map(|i| gitrepo::Rev::Commit(i.commit.clone()))
+        .context(format!(
+            "{} does not record which {} commit was used",
+            path.display(),
+            repo
+        ))
+}
+
+fn trees_diff<'a>(
+    repo: &'a gitrepo::RepoRepo,
+    old: &gitrepo::Rev,
+    new: &gitrepo::Rev,
+) -> Result<git2::Diff<'a>> {
+    let old = repo.pick(old)?.tree()?;
+    let new = repo.pick(new)?.tree()?;
+    Ok(repo
+        .repo()
+        .diff_tree_to_tree(Some(&old), Some(&new), None)?)
+}
+
+fn published_between(old: &gitrepo::Rev, new: &gitrepo::Rev) -> Result<HashSet<String>> {
+    let repo = gitrepo::RepoRepo::on_checkout(&OPTS.repo_path(RUSTSEC_REPO))?;
+    let diff = trees_diff(&repo, old, new).context("Diff rustsec commits")?;
+    Ok(diff
+        .deltas()
+        .filter(|d| d.status() == git2::Delta::Added)
+        .filter_map(|d| {
+            let path = d.new_file().path()?;
+            match path.extension()?.to_str()? {
+                "md" | "toml" => Some(path.file_stem()?.to_str()?.to_string()),
+                _ => None,
+            }
+        })
+        .collect())
+}
+
+fn yanked_between(old: &gitrepo::Rev, new: &gitrepo::Rev) -> Result<HashSet<DepInfo>> {
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(CRATES_IO_REPO))?;
+    let git = repo.repo();
+    let diff = trees_diff(&repo, old, new).context("Diff crates.io index commits")?;
+    let mut ret = HashSet::new();
+    for delta in diff.deltas() {
+        if delta.status() != git2::Delta::Modified {
+            continue;
+        }
+        let path = delta
+            .new_file()
+            .path()
+            .and_then(Path::to_str)
+            .unwrap_or_default()
+            .to_string();
+        let mut before = YankingStatus::new();
+        let mut after = YankingStatus::new();
+        let blob = git.find_blob(delta.old_file().id())?;
+        parse_index_file("", &path, blob.content(), &mut before);
+        let blob = git.find_blob(delta.new_file().id())?;
+        parse_index_file("", &path, blob.content(), &mut after);
+        for (name, versions) in after {
+            for (ver, yanked) in versions {
+                let was = before.get(&name).and_then(|vs| vs.get(&ver));
+                if yanked && was == Some(&false) {
+                    ret.insert(DepInfo {
+                        name: name.clone(),
+                        ver,
+                    });
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
+
+pub fn alert(opts: &AlertOpts) -> Result<()> {
+    let (old_path, new_path) = pick_runs(&opts.old, &opts.new)?;
+    let (old, new) = (read_output(&old_path)?, read_output(&new_path)?);
+
+    let mut published = published_between(
+        &commit_of(&old, RUSTSEC_REPO, &old_path)?,
+        &commit_of(&new, RUSTSEC_REPO, &new_path)?,
+    )?;
+    let yanked = yanked_between(
+        &commit_of(&old, CRATES_IO_REPO, &old_path)?,
+        &commit_of(&new, CRATES_IO_REPO, &new_path)?,
+    )?;
+    log::info!(
+        "{} advisories published, {} versions yanked",
+        published.len(),
+        yanked.len()
+    );
+
+    let alerts = new
+        .status
+        .into_iter()
+        .filter_map(|s| {
+            let new_advisories = s
+                .advisories
+                .iter()
+                .filter(|a| published.contains(&a.id))
+                .cloned()
+                .collect::<Vec<_>>();
+            let newly_yanked = yanked.contains(&s.id);
+            if new_advisories.is_empty() && !newly_yanked {
+                return None;
+            }
+            Some(Alert {
+                id: s.id,
+                new_advisories,
+                newly_yanked,
+                ebuilds: s.ebuilds,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut published = published.drain().collect::<Vec<_>>();
+    published.sort();
+    let alerts = Alerts { published, alerts };
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if opts.json {
+        serde_json::to_writer_pretty(&mut out, &alerts).context("Write alerts")?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    if !alerts.published.is_empty() {
+        writeln!(out, "Published: {}", alerts.published.join(" "))?;
+    }
+    for alert in &alerts.alerts {
+        writeln!(out, "{} {}", alert.id.name.as_str(), alert.id.ver)?;
+        for a in &alert.new_advisories {
+            writeln!(out, "  {}: {}", a.id, a.title)?;
+        }
+        if alert.newly_yanked {
+            writeln!(out, "  newly yanked")?;
+        }
+        for e in &alert.ebuilds {
+            writeln!(out, "    {}::{}", e.overlay, e.path)?;
+        }
+    }
+    Ok(())
+}
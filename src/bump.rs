@@ -0,0 +1,382 @@
+//! Fixes for the common case: bump vulnerable or yanked crates in an ebuild's CRATES list to their
+//! lowest safe semver-compatible version. The Manifest is left for the user to regenerate.
+
+use crate::report::read_output;
+use crate::{gitrepo, re, DepInfo, Ebuild, OPTS};
+use anyhow::{Context, Result};
+use rustsec::package::Version;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CONTEXT: usize = 3;
+
+#[derive(clap::Clap, Debug)]
+pub struct BumpOpts {
+    /// Analysis result to read, defaults to status.json in the work dir
+    #[clap(long, short = 'i')]
+    input: Option<PathBuf>,
+    /// Only bump ebuilds from these overlays
+    #[clap(long = "overlay")]
+    overlays: Vec<String>,
+    /// Instead of printing diffs, write new ebuild revisions to
+    /// <dir>/<overlay>/<category>/<package>
+    #[clap(long)]
+    write: Option<PathBuf>,
+}
+
+struct Bump {
+    from: DepInfo,
+    to: Version,
+    // Advisory ids, or yanked
+    reasons: Vec<String>,
+}
+
+pub fn bump(opts: &BumpOpts) -> Result<()> {
+    let input = opts.input.clone().unwrap_or_else(|| OPTS.status_path());
+    let output = read_output(&input)?;
+
+    let mut bumps = BTreeMap::<(String, String), Vec<Bump>>::new();
+    for status in &output.status {
+        let to = match status.upgrade.as_ref().and_then(|u| u.compatible.as_ref()) {
+            Some(to) => to,
+            None => continue,
+        };
+        let mut reasons = status
+            .advisories
+            .iter()
+            .filter(|a| !status.is_suppressed(&a.id))
+            .map(|a| a.id.clone())
+            .collect::<Vec<_>>();
+        if status.yanked == Some(true) {
+            reasons.push("yanked".to_string());
+        }
+        if reasons.is_empty() {
+            continue;
+        }
+        for ebuild in &status.ebuilds {
+            if !opts.overlays.is_empty() && !opts.overlays.contains(&ebuild.overlay) {
+                continue;
+            }
+            bumps
+                .entry((ebuild.overlay.clone(), ebuild.path.clone()))
+                .or_default()
+                .push(Bump {
+                    from: status.id.clone(),
+                    to: to.clone(),
+                    reasons: reasons.clone(),
+                });
+        }
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    for ((overlay, path), bumps) in bumps {
+        let commit = output
+            .provenance
+            .as_ref()
+            .and_then(|p| p.overlays.get(&overlay))
+            .map_or(gitrepo::Rev::Head, |i| {
+                gitrepo::Rev::Commit(i.commit.clone())
+            });
+        let ebuild = Ebuild { overlay, path };
+        let act = (|| -> Result<()> {
+            let old = read_ebuild(&ebuild, &commit)?;
+            let new = bump_crates(&old, &bumps).context("No CRATES list")?;
+            let message = commit_message(&ebuild, &bumps);
+            match &opts.write {
+                Some(dir) => {
+                    let siblings = package_files(&ebuild, &commit)?;
+                    let dest = dir.join(&ebuild.overlay).join(
+                        next_revision(&ebuild.path, &siblings).context("Strange ebuild name")?,
+                    );
+                    std::fs::create_dir_all(dest.parent().unwrap())?;
+                    std::fs::write(&dest, new).context(format!("Write {}", dest.display()))?;
+                    writeln!(out, "{}\n\n{}\n", dest.display(), message)?;
+                }
+                None => {
+                    for line in message.lines() {
+                        writeln!(out, "# {}", line)?;
+                    }
+                    write!(out, "{}", unified_diff(&ebuild.path, &old, &new))?;
+                    writeln!(out)?;
+                }
+            }
+            Ok(())
+        })();
+        if let Err(e) = act {
+            log::error!(
+                "Failed to bump {}::{}:{}",
+                ebuild.overlay,
+                ebuild.path,
+                crate::format_chain(&e)
+            );
+        }
+    }
+    Ok(())
+}
+
+fn read_ebuild(ebuild: &Ebuild, rev: &gitrepo::Rev) -> Result<String> {
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(&ebuild.overlay))?;
+    let tree = repo.pick(rev)?.tree()?;
+    let blob = tree
+        .get_path(Path::new(&ebuild.path))?
+        .to_object(repo.repo())?
+        .peel_to_blob()?;
+    Ok(String::from_utf8_lossy(blob.content()).into_owned())
+}
+
+/// Names of the files next to an ebuild
+fn package_files(ebuild: &Ebuild, rev: &gitrepo::Rev) -> Result<Vec<String>> {
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(&ebuild.overlay))?;
+    let tree = repo.pick(rev)?.tree()?;
+    let package = tree
+        .get_path(Path::new(ebuild.package()))?
+        .to_object(repo.repo())?
+        .peel_to_tree()?;
+    Ok(package
+        .iter()
+        .filter_map(|e| e.name().map(String::from))
+        .collect())
+}
+
+/// Replace the bumped entries in the CRATES list, keeping everything around them as it is.
+/// Entries whose new version is already listed are dropped, with the whitespace before them.
+fn bump_crates(content: &str, bumps: &[Bump]) -> Option<String> {
+    let list = re::CRATES.captures(content)?.get(1)?;
+    let mut listed = re::NON_WHITESPACE
+        .find_iter(list.as_str())
+        .map(|t| t.as_str().to_string())
+        .collect::<HashSet<_>>();
+    let mut ret = content[..list.start()].to_string();
+    let mut last = list.start();
+    for token in re::NON_WHITESPACE.find_iter(list.as_str()) {
+        let (start, end) = (list.start() + token.start(), list.start() + token.end());
+        let bumped = bumps
+            .iter()
+            .find(|b| token.as_str() == format!("{}-{}", b.from.name.as_str(), b.from.ver));
+        match bumped {
+            Some(b) => {
+                let to = format!("{}-{}", b.from.name.as_str(), b.to);
+                if listed.insert(to.clone()) {
+                    ret.push_str(&content[last..start]);
+                    ret.push_str(&to);
+                }
+            }
+            None => {
+                ret.push_str(&content[last..start]);
+                ret.push_str(token.as_str());
+            }
+        }
+        last = end;
+    }
+    ret.push_str(&content[last..]);
+    Some(ret)
+}
+
+fn commit_message(ebuild: &Ebuild, bumps: &[Bump]) -> String {
+    let mut ids = bumps
+        .iter()
+        .flat_map(|b| b.reasons.iter().filter(|r| *r != "yanked"))
+        .cloned()
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    let mut ret = match ids.is_empty() {
+        true => format!("{}: bump yanked crates", ebuild.package()),
+        false => format!("{}: bump crates for {}", ebuild.package(), ids.join(", ")),
+    };
+    ret.push('\n');
+    for b in bumps {
+        ret.push_str(&format!(
+            "\n{} {} -> {} ({})",
+            b.from.name.as_str(),
+            b.from.ver,
+            b.to,
+            b.reasons.join(", ")
+        ));
+    }
+    ret
+}
+
+/// category/package/package-version-r<n+1>.ebuild, n being the highest revision of the version
+/// among the files in the package directory
+fn next_revision(path: &str, siblings: &[String]) -> Option<String> {
+    let capt = re::EBUILD_DOTS.captures(path)?;
+    let revision = |capt: &regex::Captures| {
+        capt.name("rev")
+            .map_or(Ok(0), |r| r.as_str().parse::<u32>())
+            .ok()
+    };
+    let dir = &path[..path.rfind('/')?];
+    let mut rev = revision(&capt)?;
+    for name in siblings {
+        let path = format!("{}/{}", dir, name);
+        match re::EBUILD_DOTS.captures(&path) {
+            Some(c) if c["pn"] == capt["pn"] && c["ver"] == capt["ver"] => {
+                rev = rev.max(revision(&c).unwrap_or(0));
+            }
+            _ => (),
+        }
+    }
+    Some(format!(
+        "{}/{}-{}-r{}.ebuild",
+        dir,
+        &capt["pn"],
+        &capt["ver"],
+        rev + 1
+    ))
+}
+
+enum Edit<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line edits by longest common subsequence, quadratic but ebuilds are small
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ret = vec![];
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ret.push(Edit::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ret.push(Edit::Removed(old[i]));
+            i += 1;
+        } else {
+            ret.push(Edit::Added(new[j]));
+            j += 1;
+        }
+    }
+    ret
+}
+
+fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+    let edits = edits(&old, &new);
+    // Line numbers in old and new before each edit
+    let mut lines = vec![(0, 0)];
+    for e in &edits {
+        let (o, n) = lines[lines.len() - 1];
+        lines.push(match e {
+            Edit::Same(_) => (o + 1, n + 1),
+            Edit::Removed(_) => (o + 1, n),
+            Edit::Added(_) => (o, n + 1),
+        });
+    }
+    let changed = (0..edits.len())
+        .filter(|&i| !matches!(edits[i], Edit::Same(_)))
+        .collect::<Vec<_>>();
+    let mut ret = format!("--- a/{}\n+++ b/{}\n", path, path);
+    let mut i = 0;
+    while i < changed.len() {
+        let start = changed[i].saturating_sub(CONTEXT);
+        let mut j = i;
+        while j + 1 < changed.len() && changed[j + 1] <= changed[j] + 2 * CONTEXT {
+            j += 1;
+        }
+        let end = (changed[j] + CONTEXT + 1).min(edits.len());
+        let ((old_start, new_start), (old_end, new_end)) = (lines[start], lines[end]);
+        ret.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start + 1,
+            old_end - old_start,
+            new_start + 1,
+            new_end - new_start
+        ));
+        for e in &edits[start..end] {
+            match e {
+                Edit::Same(l) => ret.push_str(&format!(" {}\n", l)),
+                Edit::Removed(l) => ret.push_str(&format!("-{}\n", l)),
+                Edit::Added(l) => ret.push_str(&format!("+{}\n", l)),
+            }
+        }
+        i = j + 1;
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::dep;
+    use std::str::FromStr;
+
+    #[test]
+    fn bump_example() {
+        let old = include_str!("tests/example.ebuild");
+        let bumps = vec![Bump {
+            from: dep("arrayref", "0.3.6"),
+            to: FromStr::from_str("0.3.7").unwrap(),
+            reasons: vec!["RUSTSEC-0000-0000".to_string()],
+        }];
+        let new = bump_crates(old, &bumps).unwrap();
+        assert_eq!(old.replace("arrayref-0.3.6", "arrayref-0.3.7"), new);
+        let diff = unified_diff("a/b/b-1.ebuild", old, &new);
+        assert!(diff.contains("\n-arrayref-0.3.6\n+arrayref-0.3.7\n"));
+        assert_eq!(
+            Some("a/b/b-1-r3.ebuild".to_string()),
+            next_revision("a/b/b-1-r2.ebuild", &[])
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        let old = include_str!("tests/example.ebuild");
+        let bump = |name: &str, from: &str, to: &str| Bump {
+            from: dep(name, from),
+            to: FromStr::from_str(to).unwrap(),
+            reasons: vec!["yanked".to_string()],
+        };
+        // Already listed in the new version
+        let bumps = vec![bump("arrayref", "0.3.6", "0.3.7")];
+        let listed = old.replace("xattr-0.2.2", "arrayref-0.3.7");
+        let new = bump_crates(&listed, &bumps).unwrap();
+        assert_eq!(listed.replace("arrayref-0.3.6\n", ""), new);
+        let diff = unified_diff("a/b/b-1.ebuild", &listed, &new);
+        assert!(diff.contains("\n-arrayref-0.3.6\n arrayref-0.3.7\n"));
+        // Past the file names, nothing added
+        assert!(diff.lines().skip(2).all(|l| !l.starts_with('+')));
+        // Two versions bumped to the same one
+        let twice = old.replace("xattr-0.2.2", "adler32-1.0.5");
+        let bumps = vec![
+            bump("adler32", "1.0.4", "1.2.0"),
+            bump("adler32", "1.0.5", "1.2.0"),
+        ];
+        let new = bump_crates(&twice, &bumps).unwrap();
+        assert_eq!(1, new.matches("adler32-1.2.0").count());
+        assert!(!new.contains("adler32-1.0"));
+    }
+
+    #[test]
+    fn revisions() {
+        let siblings = vec![
+            "b-1.ebuild".to_string(),
+            "b-1-r4.ebuild".to_string(),
+            "b-10.ebuild".to_string(),
+            "b-10-r7.ebuild".to_string(),
+            "metadata.xml".to_string(),
+        ];
+        assert_eq!(
+            Some("a/b/b-1-r5.ebuild".to_string()),
+            next_revision("a/b/b-1.ebuild", &siblings)
+        );
+        assert_eq!(
+            Some("a/b/b-2-r1.ebuild".to_string()),
+            next_revision("a/b/b-2.ebuild", &siblings)
+        );
+    }
+}
@@ -0,0 +1,146 @@
+//! Moving the repositories of a work dir to machines without network access, as git bundles
+//! plus a manifest saying which bundle goes where and which commit it must provide.
+
+use crate::{gitrepo, CRATES_IO_REPO, META_REPO, OPTS, RUSTSEC_REPO};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(clap::Clap, Debug)]
+pub struct BundleOpts {
+    /// Directory holding the bundles and their manifest
+    dir: PathBuf,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    created: i64,
+    repositories: Vec<Entry>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Entry {
+    // Same naming as for analyze --commit
+    name: String,
+    overlay: bool,
+    // Relative to the manifest
+    bundle: String,
+    head: String,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+fn open(name: &str, overlay: bool, path: &Path) -> Result<gitrepo::RepoRepo> {
+    match (overlay, name) {
+        (false, RUSTSEC_REPO) => gitrepo::RepoRepo::on_checkout(path),
+        _ => gitrepo::RepoRepo::on(path),
+    }
+}
+
+pub fn export(opts: &BundleOpts) -> Result<()> {
+    let mut repos = vec![
+        (META_REPO.to_string(), false),
+        (CRATES_IO_REPO.to_string(), false),
+        (RUSTSEC_REPO.to_string(), false),
+    ];
+    let overlays = OPTS.work_dir.join("overlays");
+    if overlays.is_dir() {
+        let mut names = fs::read_dir(&overlays)?
+            .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>>>()?;
+        names.sort();
+        repos.extend(names.into_iter().map(|name| (name, true)));
+    }
+    fs::create_dir_all(opts.dir.join("overlays"))?;
+
+    let repositories = repos
+        .par_iter()
+        .map(|(name, overlay)| -> Result<Option<Entry>> {
+            let path = match overlay {
+                true => overlays.join(name),
+                false => OPTS.repo_path(name),
+            };
+            anyhow::ensure!(*overlay || path.exists(), "{} not fetched yet", name);
+            let repo = open(name, *overlay, &path)?;
+            if *overlay && repo.repo().head().is_err() {
+                log::info!("Overlay {} has no HEAD, not exporting", name);
+                return Ok(None);
+            }
+            let bundle = match overlay {
+                true => format!("overlays/{}.bundle", name),
+                false => format!("{}.bundle", name),
+            };
+            let head = repo.bundle(&opts.dir.join(&bundle))?;
+            log::info!("Exported {} at {}", name, head);
+            Ok(Some(Entry {
+                name: name.clone(),
+                overlay: *overlay,
+                bundle,
+                head: head.to_string(),
+                url: repo.url(),
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let manifest = Manifest {
+        created: chrono::Utc::now().timestamp(),
+        repositories,
+    };
+    let file = fs::File::create(opts.dir.join(MANIFEST)).context("Create manifest")?;
+    serde_json::to_writer_pretty(file, &manifest).context("Write manifest")?;
+    Ok(())
+}
+
+pub fn import(opts: &BundleOpts) -> Result<()> {
+    let file = fs::File::open(opts.dir.join(MANIFEST)).context("Open manifest")?;
+    let manifest: Manifest =
+        serde_json::from_reader(std::io::BufReader::new(file)).context("Parse manifest")?;
+    manifest
+        .repositories
+        .par_iter()
+        .try_for_each(|entry| -> Result<()> {
+            anyhow::ensure!(
+                contained(&entry.name) && contained(&entry.bundle),
+                "Refusing to import {} from {}: path leaves work or bundle dir",
+                entry.name,
+                entry.bundle
+            );
+            let path = match entry.overlay {
+                true => OPTS.work_dir.join("overlays").join(&entry.name),
+                false => OPTS.repo_path(&entry.name),
+            };
+            let head = git2::Oid::from_str(&entry.head).context("Invalid head")?;
+            let repo = open(&entry.name, entry.overlay, &path)?;
+            repo.unbundle(&opts.dir.join(&entry.bundle), head)?;
+            if let Some(url) = &entry.url {
+                repo.set_url(url)?;
+            }
+            log::info!("Imported {} at {}", entry.name, head);
+            Ok(())
+        })
+}
+
+fn contained(path: &str) -> bool {
+    Path::new(path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn containment() {
+        assert!(contained("overlays/guru.bundle"));
+        assert!(contained("crates.io"));
+        assert!(!contained("../status.json"));
+        assert!(!contained("/etc/passwd"));
+    }
+}
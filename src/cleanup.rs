@@ -0,0 +1,225 @@
+//! Vulnerable ebuilds that could simply go: often only the old versions of a package carry the
+//! vulnerable crate, and a newer ebuild in the same overlay is already fixed. Those are told apart
+//! from packages where every version is affected, which need a bump instead.
+
+use crate::{re, CrateStatus, Ebuild};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Superseded {
+    pub ebuild: Ebuild,
+    pub advisories: Vec<String>,
+    // The newest version without vulnerable crates
+    pub fixed: Ebuild,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Affected {
+    pub overlay: String,
+    pub package: String,
+    pub ebuilds: Vec<Ebuild>,
+    pub advisories: Vec<String>,
+}
+
+/// Gentoo version of an ebuild, ordered as by PMS, minus some corner cases
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EbuildVersion {
+    numbers: Vec<u64>,
+    letter: Option<char>,
+    // Rank and number; _alpha < _beta < _pre < _rc < nothing < _p, ended by nothing
+    suffixes: Vec<(u8, u64)>,
+    revision: u64,
+}
+
+impl EbuildVersion {
+    pub fn of(path: &str) -> Option<Self> {
+        let capt = re::EBUILD_DOTS.captures(path)?;
+        let ver = &capt["ver"];
+        let (main, suffixes) = ver.split_at(ver.find('_').unwrap_or(ver.len()));
+        let (main, letter) = match main.chars().last() {
+            Some(c) if c.is_ascii_lowercase() => (&main[..main.len() - 1], Some(c)),
+            _ => (main, None),
+        };
+        let mut suffixes = suffixes
+            .split('_')
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let end = s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len());
+                let rank = match &s[..end] {
+                    "alpha" => 0,
+                    "beta" => 1,
+                    "pre" => 2,
+                    "rc" => 3,
+                    _ => 5,
+                };
+                (rank, s[end..].parse().unwrap_or(0))
+            })
+            .collect::<Vec<_>>();
+        suffixes.push((4, 0));
+        Some(EbuildVersion {
+            numbers: main
+                .split('.')
+                .map(|n| n.parse().ok())
+                .collect::<Option<_>>()?,
+            letter,
+            suffixes,
+            revision: capt
+                .name("rev")
+                .map_or(Some(0), |r| r.as_str().parse().ok())?,
+        })
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.numbers.first().map_or(false, |&n| n >= 9999)
+    }
+}
+
+/// Advisories against the crates each ebuild really uses, that aren't suppressed for it
+fn vulnerabilities(crates: &[CrateStatus]) -> HashMap<&Ebuild, BTreeSet<String>> {
+    let mut ret = HashMap::<_, BTreeSet<_>>::new();
+    for c in crates {
+        let ebuilds = c
+            .ebuilds
+            .iter()
+            .filter(|e| !c.unreachable.contains(e) && !c.foreign.contains(e));
+        for ebuild in ebuilds {
+            let advisories = c
+                .advisories
+                .iter()
+                .filter(|a| a.is_vulnerability() && !a.irrelevant)
+                .filter(|a| {
+                    !c.suppressed
+                        .iter()
+                        .any(|s| s.advisory == a.id && !s.expired && s.ebuilds.contains(ebuild))
+                })
+                .map(|a| a.id.clone());
+            ret.entry(ebuild).or_default().extend(advisories);
+        }
+    }
+    ret
+}
+
+/// Vulnerable ebuilds with a fixed newer version, and packages with only vulnerable versions
+pub fn find(ebuilds: Vec<Ebuild>, crates: &[CrateStatus]) -> (Vec<Superseded>, Vec<Affected>) {
+    let mut packages = BTreeMap::<(String, String), Vec<(EbuildVersion, Ebuild)>>::new();
+    for e in ebuilds {
+        let version = match EbuildVersion::of(&e.path) {
+            Some(version) => version,
+            None => continue,
+        };
+        let key = (e.overlay.clone(), e.package().to_string());
+        packages.entry(key).or_default().push((version, e));
+    }
+    let found = vulnerabilities(crates);
+    let mut superseded = vec![];
+    let mut affected = vec![];
+    for ((overlay, package), mut versions) in packages {
+        versions.sort_by(|a, b| a.0.cmp(&b.0));
+        let vulnerable = versions
+            .iter()
+            .map(|(_, e)| found.get(e).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        if vulnerable.iter().all(|v| !v.is_empty()) {
+            affected.push(Affected {
+                overlay,
+                package,
+                advisories: vulnerable
+                    .into_iter()
+                    .flatten()
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .collect(),
+                ebuilds: versions.into_iter().map(|(_, e)| e).collect(),
+            });
+            continue;
+        }
+        let newest_fixed = |i: usize| {
+            (i + 1..versions.len())
+                .rev()
+                .find(|&j| vulnerable[j].is_empty() && !versions[j].0.is_live())
+        };
+        for (i, advisories) in vulnerable.iter().enumerate() {
+            if advisories.is_empty() {
+                continue;
+            }
+            if let Some(j) = newest_fixed(i) {
+                superseded.push(Superseded {
+                    ebuild: versions[i].1.clone(),
+                    advisories: advisories.iter().cloned().collect(),
+                    fixed: versions[j].1.clone(),
+                });
+            }
+        }
+    }
+    (superseded, affected)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{dep, ebuild, status};
+    use crate::AdvisoryMeta;
+
+    #[test]
+    fn ordering() {
+        let v = |s: &str| EbuildVersion::of(&format!("a/b/b-{}.ebuild", s)).unwrap();
+        assert!(v("1.2") < v("1.2.0"));
+        assert!(v("1.2.0") < v("1.10.0"));
+        assert!(v("1.0_rc1") < v("1.0"));
+        assert!(v("1.0") < v("1.0_p1"));
+        assert!(v("1.0_alpha2") < v("1.0_beta1"));
+        assert!(v("1.0") < v("1.0-r1"));
+        assert!(v("1.0a") > v("1.0"));
+        assert!(v("9999").is_live());
+    }
+
+    #[test]
+    fn superseded_and_affected() {
+        let e = |path: &str| ebuild("gentoo", path);
+        let advisory = |id: &str| AdvisoryMeta {
+            id: id.to_string(),
+            ..Default::default()
+        };
+        let mut smallvec = status(dep("smallvec", "1.6.0"));
+        smallvec.advisories = vec![advisory("RUSTSEC-2021-0003")];
+        smallvec.ebuilds = vec![
+            e("app-misc/foo/foo-1.0.ebuild"),
+            e("app-misc/foo/foo-9999.ebuild"),
+            e("app-misc/bar/bar-1.0.ebuild"),
+            e("app-misc/bar/bar-2.0.ebuild"),
+        ];
+        // Only listed by baz 2.0, nothing needs it there
+        smallvec.ebuilds.push(e("app-misc/baz/baz-2.0.ebuild"));
+        smallvec.unreachable = vec![e("app-misc/baz/baz-2.0.ebuild")];
+        let mut atty = status(dep("atty", "0.2.14"));
+        atty.advisories = vec![AdvisoryMeta {
+            informational: Some("unmaintained".to_string()),
+            ..advisory("RUSTSEC-2021-0145")
+        }];
+        atty.ebuilds = vec![e("app-misc/foo/foo-1.1.ebuild")];
+        let ebuilds = vec![
+            e("app-misc/foo/foo-1.0.ebuild"),
+            e("app-misc/foo/foo-1.1.ebuild"),
+            e("app-misc/foo/foo-9999.ebuild"),
+            e("app-misc/bar/bar-2.0.ebuild"),
+            e("app-misc/bar/bar-1.0.ebuild"),
+            e("app-misc/baz/baz-1.0.ebuild"),
+            e("app-misc/baz/baz-2.0.ebuild"),
+        ];
+        let (superseded, affected) = find(ebuilds, &[smallvec, atty]);
+        // Not the live ebuild, but 1.1 with only a notice
+        assert_eq!(1, superseded.len());
+        assert_eq!(e("app-misc/foo/foo-1.0.ebuild"), superseded[0].ebuild);
+        assert_eq!(e("app-misc/foo/foo-1.1.ebuild"), superseded[0].fixed);
+        assert_eq!(vec!["RUSTSEC-2021-0003"], superseded[0].advisories);
+        assert_eq!(1, affected.len());
+        assert_eq!("app-misc/bar", affected[0].package);
+        assert_eq!(
+            vec![
+                e("app-misc/bar/bar-1.0.ebuild"),
+                e("app-misc/bar/bar-2.0.ebuild")
+            ],
+            affected[0].ebuilds
+        );
+    }
+}
@@ -0,0 +1,332 @@
+//! What changed between two analysis runs
+
+use crate::report::read_output;
+use crate::{CrateStatus, DepInfo, Ebuild, Output, OPTS};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(clap::Clap, Debug)]
+pub struct DiffOpts {
+    /// Older analysis result, defaults to the second to last run stored in the work dir
+    old: Option<PathBuf>,
+    /// Newer analysis result, defaults to the last run stored in the work dir
+    new: Option<PathBuf>,
+    /// Write the differences as JSON instead of text
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Finding {
+    #[serde(rename = "crate")]
+    id: DepInfo,
+    ebuild: Ebuild,
+    advisories: Vec<String>,
+    yanked: Option<bool>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Bump {
+    overlay: String,
+    package: String,
+    removed: Vec<String>,
+    added: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Change {
+    #[serde(rename = "crate")]
+    id: DepInfo,
+    added_advisories: Vec<String>,
+    removed_advisories: Vec<String>,
+    yanked: (Option<bool>, Option<bool>),
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Diff {
+    new: Vec<Finding>,
+    resolved: Vec<Finding>,
+    bumped: Vec<Bump>,
+    changed: Vec<Change>,
+}
+
+/// Where analyze keeps a copy of each result
+pub fn runs_dir() -> PathBuf {
+    OPTS.work_dir.join("runs")
+}
+
+/// Stored runs, oldest first
+pub fn runs() -> Result<Vec<PathBuf>> {
+    let dir = runs_dir();
+    if !dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut ret = std::fs::read_dir(&dir)
+        .context(format!("List {}", dir.to_string_lossy()))?
+        .map(|e| Ok(e?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    ret.retain(|p| p.extension().map_or(false, |e| e == "json"));
+    ret.sort();
+    Ok(ret)
+}
+
+/// The two results to compare, falling back to the last two stored runs
+pub fn pick_runs(old: &Option<PathBuf>, new: &Option<PathBuf>) -> Result<(PathBuf, PathBuf)> {
+    let ret = match (old, new) {
+        (Some(old), Some(new)) => (old.clone(), new.clone()),
+        (Some(old), None) => (old.clone(), OPTS.status_path()),
+        _ => {
+            let mut runs = runs()?;
+            let new = runs.pop();
+            let old = runs.pop();
+            match (old, new) {
+                (Some(old), Some(new)) => (old, new),
+                _ => anyhow::bail!("Need two runs in {} to compare", runs_dir().display()),
+            }
+        }
+    };
+    log::info!("Comparing {} to {}", ret.0.display(), ret.1.display());
+    Ok(ret)
+}
+
+pub fn diff(opts: &DiffOpts) -> Result<()> {
+    let (old, new) = pick_runs(&opts.old, &opts.new)?;
+    let diff = compare(&read_output(&old)?, &read_output(&new)?);
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if opts.json {
+        serde_json::to_writer_pretty(&mut out, &diff).context("Write diff")?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for (title, findings) in &[("New", &diff.new), ("Resolved", &diff.resolved)] {
+        if !findings.is_empty() {
+            writeln!(out, "{} findings:", title)?;
+        }
+        for f in findings.iter() {
+            let mut what = f.advisories.clone();
+            if f.yanked == Some(true) {
+                what.push("yanked".to_string());
+            }
+            writeln!(
+                out,
+                "  {} {} ({}) in {}::{}",
+                f.id.name.as_str(),
+                f.id.ver,
+                what.join(", "),
+                f.ebuild.overlay,
+                f.ebuild.path
+            )?;
+        }
+    }
+    if !diff.bumped.is_empty() {
+        writeln!(out, "Bumped packages:")?;
+    }
+    for b in &diff.bumped {
+        writeln!(
+            out,
+            "  {}::{}: {} -> {}",
+            b.overlay,
+            b.package,
+            b.removed.join(" "),
+            b.added.join(" ")
+        )?;
+    }
+    if !diff.changed.is_empty() {
+        writeln!(out, "Changed crates:")?;
+    }
+    for c in &diff.changed {
+        let mut what = vec![];
+        what.extend(c.added_advisories.iter().map(|a| format!("+{}", a)));
+        what.extend(c.removed_advisories.iter().map(|a| format!("-{}", a)));
+        if c.yanked.0 != c.yanked.1 {
+            what.push(format!("yanked: {:?} -> {:?}", c.yanked.0, c.yanked.1));
+        }
+        writeln!(
+            out,
+            "  {} {}: {}",
+            c.id.name.as_str(),
+            c.id.ver,
+            what.join(", ")
+        )?;
+    }
+    Ok(())
+}
+
+fn is_finding(status: &CrateStatus) -> bool {
+    !status.advisories.is_empty() || status.yanked == Some(true)
+}
+
+fn advisory_ids(status: &CrateStatus) -> BTreeSet<String> {
+    status.advisories.iter().map(|a| a.id.clone()).collect()
+}
+
+type Findings<'a> = HashMap<(&'a DepInfo, &'a Ebuild), &'a CrateStatus>;
+
+fn findings(output: &Output) -> Findings {
+    output
+        .status
+        .iter()
+        .filter(|s| is_finding(s))
+        .flat_map(|s| s.ebuilds.iter().map(move |e| ((&s.id, e), s)))
+        .collect()
+}
+
+fn only(a: &Findings, b: &Findings) -> Vec<Finding> {
+    let mut ret = a
+        .iter()
+        .filter(|(k, _)| !b.contains_key(*k))
+        .map(|((id, ebuild), status)| Finding {
+            id: (*id).clone(),
+            ebuild: (*ebuild).clone(),
+            advisories: advisory_ids(status).into_iter().collect(),
+            yanked: status.yanked,
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| {
+        (
+            &a.ebuild.overlay,
+            &a.ebuild.path,
+            a.id.name.as_str(),
+            &a.id.ver,
+        )
+            .cmp(&(
+                &b.ebuild.overlay,
+                &b.ebuild.path,
+                b.id.name.as_str(),
+                &b.id.ver,
+            ))
+    });
+    ret
+}
+
+fn compare(old: &Output, new: &Output) -> Diff {
+    let old_findings = findings(old);
+    let new_findings = findings(new);
+    let ebuilds = |output: &Output| {
+        output
+            .status
+            .iter()
+            .flat_map(|s| s.ebuilds.iter().cloned())
+            .collect::<HashSet<_>>()
+    };
+    let old_ebuilds = ebuilds(old);
+    let new_ebuilds = ebuilds(new);
+    let mut bumps = BTreeMap::<(String, String), (Vec<String>, Vec<String>)>::new();
+    for e in old_ebuilds.difference(&new_ebuilds) {
+        let key = (e.overlay.clone(), e.package().to_string());
+        bumps.entry(key).or_default().0.push(e.path.clone());
+    }
+    for e in new_ebuilds.difference(&old_ebuilds) {
+        let key = (e.overlay.clone(), e.package().to_string());
+        bumps.entry(key).or_default().1.push(e.path.clone());
+    }
+    let bumped = bumps
+        .into_iter()
+        .filter(|(_, (removed, added))| !removed.is_empty() && !added.is_empty())
+        .map(|((overlay, package), (mut removed, mut added))| {
+            removed.sort();
+            added.sort();
+            Bump {
+                overlay,
+                package,
+                removed,
+                added,
+            }
+        })
+        .collect();
+
+    let old_crates = old
+        .status
+        .iter()
+        .map(|s| (&s.id, s))
+        .collect::<HashMap<_, _>>();
+    let mut changed = new
+        .status
+        .iter()
+        .filter_map(|n| {
+            let o = old_crates.get(&n.id)?;
+            let (oa, na) = (advisory_ids(o), advisory_ids(n));
+            if oa == na && o.yanked == n.yanked {
+                return None;
+            }
+            Some(Change {
+                id: n.id.clone(),
+                added_advisories: na.difference(&oa).cloned().collect(),
+                removed_advisories: oa.difference(&na).cloned().collect(),
+                yanked: (o.yanked, n.yanked),
+            })
+        })
+        .collect::<Vec<_>>();
+    changed.sort_by(|a, b| (a.id.name.as_str(), &a.id.ver).cmp(&(b.id.name.as_str(), &b.id.ver)));
+
+    Diff {
+        new: only(&new_findings, &old_findings),
+        resolved: only(&old_findings, &new_findings),
+        bumped,
+        changed,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{dep, ebuild};
+
+    fn status(name: &str, ver: &str, advisories: &[&str], ebuilds: &[&str]) -> CrateStatus {
+        CrateStatus {
+            advisories: advisories
+                .iter()
+                .map(|id| crate::AdvisoryMeta {
+                    id: id.to_string(),
+                    title: String::new(),
+                    cvss: None,
+                    source: crate::RUSTSEC_REPO.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ebuilds: ebuilds.iter().map(|path| ebuild("gentoo", path)).collect(),
+            ..crate::testing::status(dep(name, ver))
+        }
+    }
+
+    #[test]
+    fn compare_runs() {
+        let old = Output {
+            status: vec![
+                status(
+                    "time",
+                    "0.1.43",
+                    &["RUSTSEC-2020-0071"],
+                    &["a/b/b-1.ebuild"],
+                ),
+                status("chrono", "0.4.19", &[], &["a/b/b-1.ebuild"]),
+            ],
+            ..Default::default()
+        };
+        let new = Output {
+            status: vec![
+                status("time", "0.1.44", &[], &["a/b/b-2.ebuild"]),
+                status(
+                    "chrono",
+                    "0.4.19",
+                    &["RUSTSEC-2020-0159"],
+                    &["a/b/b-2.ebuild"],
+                ),
+            ],
+            ..Default::default()
+        };
+        let diff = compare(&old, &new);
+        assert_eq!(1, diff.new.len());
+        assert_eq!("chrono", diff.new[0].id.name.as_str());
+        assert_eq!(1, diff.resolved.len());
+        assert_eq!("time", diff.resolved[0].id.name.as_str());
+        assert_eq!(1, diff.bumped.len());
+        assert_eq!(vec!["a/b/b-2.ebuild"], diff.bumped[0].added);
+        assert_eq!(1, diff.changed.len());
+        assert_eq!(vec!["RUSTSEC-2020-0159"], diff.changed[0].added_advisories);
+    }
+}
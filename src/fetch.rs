@@ -0,0 +1,108 @@
+use crate::{
+    fgo, format_chain, gitrepo, overlays, provenance, CRATES_IO_REPO, GENTOO_META_REPO_ORIGIN,
+    META_REPO, OPTS, RUSTSEC_REPO,
+};
+use anyhow::{Context, Result};
+use crossbeam_utils::atomic::AtomicCell;
+
+pub fn fetch() -> Result<()> {
+    gitrepo::RepoRepo::on(&OPTS.repo_path(META_REPO))?
+        .up(GENTOO_META_REPO_ORIGIN)
+        .context("Fetch gentoo overlay list")?;
+    let overlays = fgo(&provenance::Recorder::default())?;
+    let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
+
+    let mut cio_get = Err(anyhow::anyhow!("crates.io not retrieved"));
+    let mut rustsec_get = Err(anyhow::anyhow!("Rustsec repo not retrieved"));
+    let gentoo_overlay_status = AtomicCell::new(Err(anyhow::anyhow!("gentoo overlay not fetched")));
+
+    pool.scope(|scope| {
+        scope.spawn(|_| {
+            rustsec_get = (|| -> Result<_> {
+                gitrepo::RepoRepo::on_checkout(&OPTS.repo_path(RUSTSEC_REPO))?
+                    .up(rustsec::repository::git::DEFAULT_URL)?;
+                Ok(())
+            })()
+            .context("Get rustsec");
+        });
+        scope.spawn(|_| {
+            cio_get = (|| -> Result<_> {
+                gitrepo::RepoRepo::on(&OPTS.repo_path(CRATES_IO_REPO))?
+                    .up(cargo::sources::registry::CRATES_IO_INDEX)?;
+                Ok(())
+            })()
+            .context("Get crates.io index");
+        });
+        for overlay in overlays {
+            scope.spawn(|_scope| {
+                let overlay = overlay;
+                let act = fetch_overlay(&overlay);
+                if overlay.name == "gentoo" {
+                    gentoo_overlay_status.store(act);
+                } else if let Err(e) = act {
+                    log::error!(
+                        "Failed to fetch overlay {}:{}",
+                        overlay.name,
+                        format_chain(&e),
+                    );
+                };
+            });
+        }
+    });
+
+    cio_get?;
+    rustsec_get?;
+    gentoo_overlay_status.swap(Ok(()))?;
+    Ok(())
+}
+
+fn fetch_overlay(overlay: &overlays::Overlay) -> Result<()> {
+    fn source_goodness(url: &str) -> i8 {
+        if url.starts_with("git://") {
+            -2
+        } else if url.starts_with("https://") {
+            -1
+        } else if url.starts_with("git@") {
+            1
+        } else if url.starts_with("git+ssh://") {
+            2
+        } else if url.starts_with("ssh+git://") {
+            2
+        } else {
+            0
+        }
+    }
+    let mut sources = overlay
+        .sources
+        .iter()
+        .filter(|s| s.typ == overlays::SourceType::Git)
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        log::info!("No git source for {}, ignoring", overlay.name);
+        return Ok(());
+    };
+    sources.sort_by_key(|s| source_goodness(&s.url));
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(&overlay.name))?;
+
+    for source in sources {
+        match repo.up(&source.url) {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                log::error!(
+                    "Failed to update overlay {} with source {}:{}",
+                    overlay.name,
+                    source.url,
+                    format_chain(&e),
+                );
+            }
+        }
+    }
+    repo.repo()
+        .head()
+        .context("All sources failed and there is no previous HEAD")?;
+    log::warn!(
+        "Overlay {}: all sources failed, keeping previous HEAD",
+        overlay.name
+    );
+    Ok(())
+}
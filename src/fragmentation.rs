@@ -0,0 +1,129 @@
+//! How many versions of each crate the ebuilds use between them. Every version is a distfile on
+//! the mirrors; versions on the same semver-compatible line could be replaced by the newest one
+//! without breaking anything, at least in theory.
+
+use crate::EbuildDeps;
+use rustsec::package::{Name, Version};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Fragmentation {
+    #[serde(rename = "crate")]
+    pub name: Name,
+    // Distinct versions in use
+    pub versions: usize,
+    // Ebuilds using any version
+    pub ebuilds: usize,
+    // Semver-incompatible lines, oldest first
+    pub lines: Vec<Line>,
+    // Distfiles fewer if all ebuilds used the newest version of each line
+    pub savings: usize,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Line {
+    // e.g. 1, 0.2 or 0.0.3
+    pub line: String,
+    pub versions: Vec<Version>,
+}
+
+/// The semver-compatible line a version belongs to, as in cargo's ^ requirements
+fn line(v: &Version) -> String {
+    match (v.major, v.minor) {
+        (0, 0) => format!("0.0.{}", v.patch),
+        (0, minor) => format!("0.{}", minor),
+        (major, _) => major.to_string(),
+    }
+}
+
+/// Crates used in more than one version, most savings first
+pub fn stats(deps: &EbuildDeps) -> Vec<Fragmentation> {
+    let mut used = HashMap::<Name, (HashSet<Version>, usize)>::new();
+    for e in deps.iter() {
+        let mut seen = HashSet::new();
+        for dep in &e.value().deps {
+            let (versions, ebuilds) = used.entry(dep.name.clone()).or_default();
+            versions.insert(dep.ver.clone());
+            if seen.insert(&dep.name) {
+                *ebuilds += 1;
+            }
+        }
+    }
+    let mut ret = used
+        .into_iter()
+        .filter(|(_, (versions, _))| versions.len() > 1)
+        .map(|(name, (versions, ebuilds))| {
+            let mut lines = BTreeMap::<(u64, u64, u64), Line>::new();
+            for v in versions.iter() {
+                // Order lines by their oldest possible version
+                let key = match (v.major, v.minor) {
+                    (0, 0) => (0, 0, v.patch),
+                    (0, minor) => (0, minor, 0),
+                    (major, _) => (major, 0, 0),
+                };
+                lines
+                    .entry(key)
+                    .or_insert_with(|| Line {
+                        line: line(v),
+                        versions: vec![],
+                    })
+                    .versions
+                    .push(v.clone());
+            }
+            let lines = lines
+                .into_iter()
+                .map(|(_, mut l)| {
+                    l.versions.sort();
+                    l
+                })
+                .collect::<Vec<_>>();
+            Fragmentation {
+                name,
+                versions: versions.len(),
+                ebuilds,
+                savings: versions.len() - lines.len(),
+                lines,
+            }
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| {
+        (b.savings, b.versions, a.name.as_str()).cmp(&(a.savings, a.versions, b.name.as_str()))
+    });
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{add_ebuild, dep};
+
+    #[test]
+    fn savings() {
+        let deps = EbuildDeps::new();
+        let add = |path: &str, crates: &[(&str, &str)]| {
+            let crates = crates
+                .iter()
+                .map(|(name, ver)| dep(name, ver))
+                .collect::<Vec<_>>();
+            add_ebuild(&deps, path, &crates, "");
+        };
+        add("a/a/a-1.ebuild", &[("syn", "1.0.60"), ("libc", "0.2.80")]);
+        add("b/b/b-1.ebuild", &[("syn", "1.0.72"), ("libc", "0.2.80")]);
+        add(
+            "c/c/c-1.ebuild",
+            &[("syn", "0.15.44"), ("syn", "1.0.72"), ("bytes", "1.0.1")],
+        );
+        let stats = stats(&deps);
+        assert_eq!(1, stats.len());
+        let syn = &stats[0];
+        assert_eq!("syn", syn.name.as_str());
+        assert_eq!((3, 3, 1), (syn.versions, syn.ebuilds, syn.savings));
+        let lines = syn
+            .lines
+            .iter()
+            .map(|l| l.line.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["0.15", "1"], lines);
+        assert_eq!(2, syn.lines[1].versions.len());
+    }
+}
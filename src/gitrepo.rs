@@ -2,6 +2,7 @@ use anyhow::{Context, Result};
 use std::borrow::Cow;
 use std::fs;
 use std::path::Path;
+use std::process::Command;
 
 // Brr. cargo doesn't expose the function, rustsec copies and modifies it, with its own result type.
 fn with_git_default_auth<T, F>(url: &str, mut f: F) -> T
@@ -20,6 +21,16 @@ pub struct RepoRepo {
     repo: git2::Repository,
 }
 
+/// Which commit of a repository to analyse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rev {
+    Head,
+    /// Last commit (following first parents from HEAD) with a commit time at or before this unix timestamp
+    Before(i64),
+    /// Anything git rev-parse understands, usually a commit id
+    Commit(String),
+}
+
 impl RepoRepo {
     pub fn on(path: &Path) -> Result<Self> {
         Self::open(path, true).context("Open bare repository")
@@ -53,13 +64,72 @@ impl RepoRepo {
         Ok(RepoRepo { repo })
     }
 
-    pub fn up_or_head(&self, url: &str, head: bool) -> Result<git2::Reference> {
-        Ok(match head {
-            true => self.repo.head()?,
-            false => self.up(url).context("Update")?,
+    pub fn pick(&self, rev: &Rev) -> Result<git2::Commit> {
+        let head = || -> Result<_> { Ok(self.repo.head()?.peel_to_commit()?) };
+        Ok(match rev {
+            Rev::Head => head().context("Resolve HEAD")?,
+            Rev::Before(time) => {
+                let mut walk = self.repo.revwalk()?;
+                walk.push(head().context("Resolve HEAD")?.id())?;
+                walk.simplify_first_parent()?;
+                let mut found = None;
+                for oid in walk {
+                    let commit = self.repo.find_commit(oid?)?;
+                    if commit.time().seconds() <= *time {
+                        found = Some(commit);
+                        break;
+                    }
+                }
+                found.context(format!(
+                    "No commit at or before {} in {}",
+                    time,
+                    self.path()
+                ))?
+            }
+            Rev::Commit(spec) => self
+                .repo
+                .revparse_single(spec)
+                .and_then(|o| o.peel_to_commit())
+                .context(format!("Resolve {} in {}", spec, self.path()))?,
         })
     }
-    fn up(&self, url: &str) -> Result<git2::Reference> {
+    /// Write out the files of a tree, e.g. for consumers that can't read from git objects
+    pub fn extract(&self, tree: &git2::Tree, dest: &Path) -> Result<()> {
+        let mut err = Ok(());
+        let walked = tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+            let act = || -> Result<()> {
+                let path = dest
+                    .join(root)
+                    .join(entry.name().context("Non-UTF-8 name")?);
+                match entry.kind() {
+                    Some(git2::ObjectType::Tree) => fs::create_dir_all(&path)?,
+                    Some(git2::ObjectType::Blob) => fs::write(
+                        &path,
+                        entry
+                            .to_object(&self.repo)?
+                            .as_blob()
+                            .context("Object blob")?
+                            .content(),
+                    )?,
+                    _ => (),
+                }
+                Ok(())
+            };
+            match act() {
+                Ok(()) => git2::TreeWalkResult::Ok,
+                Err(e) => {
+                    err = Err(e);
+                    git2::TreeWalkResult::Abort
+                }
+            }
+        });
+        err.and(walked.map_err(Into::into)).context(format!(
+            "Extract tree {} to {}",
+            tree.id(),
+            dest.display()
+        ))
+    }
+    pub fn up(&self, url: &str) -> Result<git2::Reference> {
         let url = match url.starts_with("ssh+git://") || url.starts_with("git+ssh://") {
             true => &url[10..],
             false => url,
@@ -124,21 +194,11 @@ impl RepoRepo {
 
             if !self.repo.is_bare() {
                 // TODO: should this also be done in offline mode?
-                self.repo
-                    .checkout_tree(
-                        head.peel_to_tree()
-                            .context("HEAD does not point to tree")?
-                            .as_object(),
-                        Some(
-                            git2::build::CheckoutBuilder::new()
-                                .force()
-                                .remove_untracked(true)
-                                .remove_ignored(true),
-                        ),
-                    )
-                    .context("Update checked-out tree")?;
+                self.checkout(&head.peel_to_tree().context("HEAD does not point to tree")?)?;
             }
 
+            self.set_url(url)?;
+
             // TODO: Prune
 
             Ok(head)
@@ -146,6 +206,64 @@ impl RepoRepo {
         .context(format!("Fetch {} to {}", url, self.path()))?)
     }
 
+    fn checkout(&self, tree: &git2::Tree) -> Result<()> {
+        self.repo
+            .checkout_tree(
+                tree.as_object(),
+                Some(
+                    git2::build::CheckoutBuilder::new()
+                        .force()
+                        .remove_untracked(true)
+                        .remove_ignored(true),
+                ),
+            )
+            .context("Update checked-out tree")
+    }
+
+    // libgit2 can't do bundles, so these go through the git binary
+    pub fn bundle(&self, file: &Path) -> Result<git2::Oid> {
+        let head = self.repo.head()?.peel_to_commit()?.id();
+        let mut git = self.git();
+        git.arg("bundle").arg("create").arg(file).arg("HEAD");
+        run(git).context(format!("Bundle {} to {}", self.path(), file.display()))?;
+        Ok(head)
+    }
+    pub fn unbundle(&self, file: &Path, head: git2::Oid) -> Result<()> {
+        let mut git = self.git();
+        git.arg("fetch").arg(file).arg("HEAD");
+        run(git).context(format!("Unbundle {} to {}", file.display(), self.path()))?;
+        let commit = self.repo.find_commit(head).context(format!(
+            "Bundle {} does not contain {}",
+            file.display(),
+            head
+        ))?;
+        self.repo.set_head_detached(head).context("Store head")?;
+        if !self.repo.is_bare() {
+            self.checkout(&commit.tree()?)?;
+        }
+        Ok(())
+    }
+    fn git(&self) -> Command {
+        let mut git = Command::new("git");
+        git.arg("--git-dir").arg(self.repo.path());
+        git
+    }
+
+    /// Where the repository was last fetched from
+    pub fn url(&self) -> Option<String> {
+        self.repo
+            .config()
+            .ok()?
+            .get_string("remote.origin.url")
+            .ok()
+    }
+    pub fn set_url(&self, url: &str) -> Result<()> {
+        self.repo
+            .config()?
+            .set_str("remote.origin.url", url)
+            .context("Store origin URL")
+    }
+
     pub fn path(&self) -> Cow<'_, str> {
         self.repo.path().to_string_lossy()
     }
@@ -154,3 +272,9 @@ impl RepoRepo {
         &self.repo
     }
 }
+
+fn run(mut cmd: Command) -> Result<()> {
+    let status = cmd.status().context(format!("Run {:?}", cmd))?;
+    anyhow::ensure!(status.success(), "{:?} failed: {}", cmd, status);
+    Ok(())
+}
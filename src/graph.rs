@@ -0,0 +1,300 @@
+//! The dependency graph among the crates of one CRATES list, as far as the index can tell: each
+//! dependency requirement resolves to the highest listed version matching it. The root is the
+//! package's own crate if it is listed. Otherwise, the crates no other listed crate depends on are
+//! taken as the roots, usually the package's direct dependencies. Leftovers from a bump look just
+//! the same then, there's no telling them apart from the CRATES list alone.
+//! Crates not reachable from the package's own crate are probably not needed at all. Without it,
+//! everything counts as reachable.
+//! Requirements that no listed version satisfies mean the list is incomplete: the build needs the
+//! network or fails.
+//! Dependencies for specific targets are evaluated for Linux on the ebuild's arches; crates only
+//! reachable through other targets are foreign, e.g. winapi.
+
+use crate::{index, target, DepInfo, Ebuild, RegistryDependency};
+use rustsec::VersionReq;
+use std::collections::{HashMap, VecDeque};
+
+pub struct Graph {
+    pub nodes: Vec<DepInfo>,
+    // The dependencies of each node
+    pub edges: Vec<Vec<Edge>>,
+    pub roots: Vec<usize>,
+    // Whether the root is the package's own crate, which unreachable crates can only be told from
+    pub own_root: bool,
+    // Non-optional requirements no listed crate satisfies
+    pub unsatisfied: Vec<Unsatisfied>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Edge {
+    pub to: usize,
+    // Which of the index entry's dependencies
+    pub dep: usize,
+    // False if only used on other targets than Linux
+    pub linux: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Unsatisfied {
+    pub from: DepInfo,
+    #[serde(rename = "crate")]
+    pub name: String,
+    pub req: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Unreachable {
+    pub ebuild: Ebuild,
+    pub crates: Vec<DepInfo>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Incomplete {
+    pub ebuild: Ebuild,
+    pub unsatisfied: Vec<Unsatisfied>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DepPath {
+    pub ebuild: Ebuild,
+    // From a root to the crate itself
+    pub path: Vec<DepInfo>,
+}
+
+impl RegistryDependency {
+    /// The name of the crate on crates.io, not the one it's renamed to
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+    pub fn is_dev(&self) -> bool {
+        self.kind.as_deref() == Some("dev")
+    }
+}
+
+/// root: name of the package's own crate, arches: the ebuild's, as Rust names
+pub fn build(
+    crates: &[DepInfo],
+    index: &index::Entries,
+    root: Option<&str>,
+    arches: &[String],
+) -> Graph {
+    let mut by_name = HashMap::<&str, Vec<usize>>::new();
+    for (i, c) in crates.iter().enumerate() {
+        by_name.entry(c.name.as_str()).or_default().push(i);
+    }
+    let mut edges = crates.iter().map(|_| vec![]).collect::<Vec<_>>();
+    let mut unsatisfied = vec![];
+    for (from, c) in crates.iter().enumerate() {
+        let entry = match index.get(&c.name).and_then(|vs| vs.get(&c.ver)) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        for (i, dep) in entry.deps.iter().enumerate().filter(|(_, d)| !d.is_dev()) {
+            let req = match VersionReq::parse(&dep.req) {
+                Ok(req) => req,
+                Err(e) => {
+                    log::warn!(
+                        "{} {}: bad requirement {}: {}",
+                        c.name.as_str(),
+                        c.ver,
+                        dep.req,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let to = by_name
+                .get(dep.crate_name())
+                .into_iter()
+                .flatten()
+                .copied()
+                .filter(|&i| req.matches(&crates[i].ver))
+                .max_by(|&a, &b| crates[a].ver.cmp(&crates[b].ver));
+            match to {
+                Some(to) => edges[from].push(Edge {
+                    to,
+                    dep: i,
+                    linux: dep
+                        .target
+                        .as_deref()
+                        .map_or(true, |t| target::active_on_linux(t, arches)),
+                }),
+                None if !dep.optional => unsatisfied.push(Unsatisfied {
+                    from: c.clone(),
+                    name: dep.crate_name().to_string(),
+                    req: dep.req.clone(),
+                }),
+                None => (),
+            }
+        }
+    }
+    let (roots, own_root) = roots(crates, &edges, root);
+    Graph {
+        nodes: crates.to_vec(),
+        edges,
+        roots,
+        own_root,
+        unsatisfied,
+    }
+}
+
+/// The roots, and whether they are the package's own crate. Crate names compare like crates.io
+/// does, ignoring case and - vs. _.
+fn roots(crates: &[DepInfo], edges: &[Vec<Edge>], root: Option<&str>) -> (Vec<usize>, bool) {
+    let normalize = |name: &str| name.to_lowercase().replace('_', "-");
+    let root = root.map(normalize);
+    let own = (0..crates.len())
+        .filter(|&i| Some(normalize(crates[i].name.as_str())) == root)
+        .collect::<Vec<_>>();
+    if !own.is_empty() {
+        return (own, true);
+    }
+    let mut has_parent = vec![false; crates.len()];
+    for (from, edges) in edges.iter().enumerate() {
+        for e in edges {
+            has_parent[e.to] |= e.to != from;
+        }
+    }
+    let roots = (0..crates.len()).filter(|&i| !has_parent[i]).collect();
+    (roots, false)
+}
+
+impl Graph {
+    /// Whether each crate can be reached from a root, possibly only through dependencies used on
+    /// Linux
+    pub fn reachable(&self, linux: bool) -> Vec<bool> {
+        let mut ret = vec![false; self.nodes.len()];
+        let mut stack = self.roots.clone();
+        while let Some(n) = stack.pop() {
+            if !ret[n] {
+                ret[n] = true;
+                stack.extend(
+                    self.edges[n]
+                        .iter()
+                        .filter(|e| e.linux || !linux)
+                        .map(|e| e.to),
+                );
+            }
+        }
+        ret
+    }
+
+    /// Shortest path from each root that reaches the crate at target
+    pub fn paths_to(&self, target: usize) -> Vec<Vec<usize>> {
+        let mut reverse = vec![vec![]; self.nodes.len()];
+        for (from, edges) in self.edges.iter().enumerate() {
+            for e in edges {
+                reverse[e.to].push(from);
+            }
+        }
+        // Breadth first from the target against the edges, remembering the way back
+        let mut next = vec![None; self.nodes.len()];
+        next[target] = Some(target);
+        let mut queue = VecDeque::from(vec![target]);
+        while let Some(n) = queue.pop_front() {
+            for &p in &reverse[n] {
+                if next[p].is_none() {
+                    next[p] = Some(n);
+                    queue.push_back(p);
+                }
+            }
+        }
+        self.roots
+            .iter()
+            .copied()
+            .filter(|&root| next[root].is_some())
+            .map(|root| {
+                let mut path = vec![root];
+                let mut n = root;
+                while n != target {
+                    n = next[n].unwrap();
+                    path.push(n);
+                }
+                path
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{add_package, dep};
+
+    #[test]
+    fn paths() {
+        let crates = vec![
+            dep("app", "1.0.0"),
+            dep("hyper", "0.14.2"),
+            dep("tokio", "1.1.0"),
+            dep("tokio", "0.2.25"),
+            dep("bytes", "1.0.1"),
+            dep("winapi", "0.3.9"),
+        ];
+        let mut index = index::Entries::new();
+        let mut add = |c: &DepInfo, deps: &[(&str, &str, Option<&str>)]| {
+            let deps = deps
+                .iter()
+                .map(|(name, req, target)| {
+                    serde_json::json!({"name": name, "req": req, "target": target})
+                })
+                .collect::<Vec<_>>();
+            add_package(&mut index, c, serde_json::json!({ "deps": deps }));
+        };
+        add(
+            &crates[0],
+            &[
+                ("hyper", "^0.14", None),
+                ("bytes", "^1", None),
+                ("serde", "^1", None),
+            ],
+        );
+        add(&crates[1], &[("tokio", "^1", None)]);
+        add(
+            &crates[2],
+            &[
+                ("bytes", "^1", None),
+                ("winapi", "^0.3", Some("cfg(windows)")),
+            ],
+        );
+        let graph = build(&crates, &index, None, &[]);
+        assert_eq!(vec![0, 3], graph.roots);
+        assert_eq!(vec![vec![0, 1, 2]], graph.paths_to(2));
+        assert_eq!(vec![vec![0, 4]], graph.paths_to(4));
+        // Leftover 0.2 tokio isn't used by anything, so it could as well be a direct dependency
+        assert_eq!(vec![vec![3]], graph.paths_to(3));
+        assert_eq!(vec![true; 6], graph.reachable(false));
+        // winapi only on Windows
+        assert_eq!(
+            vec![true, true, true, true, true, false],
+            graph.reachable(true)
+        );
+        assert!(!graph.own_root);
+        let own = build(&crates, &index, Some("Hyper"), &[]);
+        assert_eq!((vec![1], true), (own.roots, own.own_root));
+        assert_eq!(1, graph.unsatisfied.len());
+        assert_eq!("serde", graph.unsatisfied[0].name);
+    }
+
+    #[test]
+    fn versions() {
+        // tokio 1 is a direct dependency, 0.2 comes in through hyper 0.13
+        let crates = vec![
+            dep("tokio", "1.1.0"),
+            dep("hyper", "0.13.9"),
+            dep("tokio", "0.2.25"),
+        ];
+        let mut index = index::Entries::new();
+        add_package(&mut index, &crates[0], serde_json::json!({}));
+        add_package(
+            &mut index,
+            &crates[1],
+            serde_json::json!({"deps": [{"name": "tokio", "req": "^0.2"}]}),
+        );
+        add_package(&mut index, &crates[2], serde_json::json!({}));
+        let graph = build(&crates, &index, None, &[]);
+        assert_eq!(vec![0, 1], graph.roots);
+        assert_eq!(vec![vec![1, 2]], graph.paths_to(2));
+        assert_eq!(vec![true; 3], graph.reachable(true));
+    }
+}
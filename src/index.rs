@@ -0,0 +1,75 @@
+//! Full crates.io index entries for the crates used by ebuilds. The walk in cio only keeps the
+//! yanked flag of every version; the rest is read afterwards, for the few crates that matter.
+
+use crate::{gitrepo, RegistryPackage, CRATES_IO_REPO, OPTS};
+use anyhow::{Context, Result};
+use rustsec::package::{Name, Version};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+pub type Entries = HashMap<Name, HashMap<Version, RegistryPackage>>;
+
+/// Where the index keeps a crate, e.g. se/rd/serde or 3/s/syn
+fn path_of(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+/// Index entries of the given crates, from the same crates.io commit cio reads
+pub fn load<'a>(names: impl Iterator<Item = &'a Name>) -> Result<Entries> {
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(CRATES_IO_REPO))?;
+    let tree = repo.pick(&OPTS.rev(CRATES_IO_REPO))?.tree()?;
+    let mut ret = Entries::new();
+    for name in names {
+        if ret.contains_key(name) {
+            continue;
+        }
+        let path = path_of(name.as_str());
+        let entry = match tree.get_path(Path::new(&path)) {
+            Ok(entry) => entry,
+            Err(_) => continue, // Not on crates.io, the yanked status says so already
+        };
+        let blob = entry
+            .to_object(repo.repo())?
+            .peel_to_blob()
+            .context(format!("Read index file {}", path))?;
+        ret.insert(name.clone(), parse(&path, blob.content()));
+    }
+    Ok(ret)
+}
+
+fn parse(path: &str, content: &[u8]) -> HashMap<Version, RegistryPackage> {
+    use std::io::BufRead;
+    let mut ret = HashMap::new();
+    for (i, line) in content.lines().enumerate() {
+        let parsed = (|| -> Result<_> {
+            let info = serde_json::from_str::<RegistryPackage>(&line?)?;
+            Ok((Version::from_str(&info.vers)?, info))
+        })();
+        match parsed {
+            Ok((vers, info)) => {
+                ret.insert(vers, info);
+            }
+            Err(e) => log::error!("Cannot parse crate info for {}:{}: {}", path, i + 1, e),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths() {
+        assert_eq!("1/a", path_of("a"));
+        assert_eq!("3/s/syn", path_of("syn"));
+        assert_eq!("se/rd/serde", path_of("Serde"));
+    }
+}
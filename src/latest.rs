@@ -0,0 +1,98 @@
+//! Auditing only the newest ebuild of each package in each overlay, or the newest with a stable
+//! keyword: what users get on update, rather than every ebuild file that is still around.
+
+use crate::cleanup::EbuildVersion;
+use crate::{Ebuild, EbuildDeps};
+use std::collections::{HashMap, HashSet};
+
+/// Whether any keyword marks the ebuild stable, e.g. amd64 as opposed to ~amd64 or -*
+fn is_stable(keywords: &[String]) -> bool {
+    keywords
+        .iter()
+        .any(|k| !k.starts_with('~') && !k.starts_with('-') && k != "*")
+}
+
+/// Drop all but the newest ebuild of each package, skipping live ebuilds. Packages left without
+/// any ebuild are logged.
+pub fn collapse(deps: &EbuildDeps, stable: bool) {
+    let mut newest = HashMap::<(String, String), (EbuildVersion, Ebuild)>::new();
+    let mut packages = HashSet::new();
+    for e in deps.iter() {
+        let key = (e.key().overlay.clone(), e.key().package().to_string());
+        packages.insert(key.clone());
+        if stable && !is_stable(&e.value().keywords) {
+            continue;
+        }
+        let version = match EbuildVersion::of(&e.key().path) {
+            Some(version) if !version.is_live() => version,
+            Some(_) => continue,
+            None => {
+                log::warn!("Can't order {}::{}, skipped", e.key().overlay, e.key().path);
+                continue;
+            }
+        };
+        match newest.get(&key) {
+            Some((v, _)) if v >= &version => (),
+            _ => {
+                newest.insert(key, (version, e.key().clone()));
+            }
+        }
+    }
+    let mut dropped = packages
+        .into_iter()
+        .filter(|p| !newest.contains_key(p))
+        .collect::<Vec<_>>();
+    dropped.sort();
+    for (overlay, package) in dropped {
+        log::info!(
+            "Not auditing {}::{}, no {}ebuild of a release",
+            overlay,
+            package,
+            match stable {
+                true => "stable ",
+                false => "",
+            }
+        );
+    }
+    let keep = newest
+        .into_iter()
+        .map(|(_, (_, e))| e)
+        .collect::<HashSet<_>>();
+    let before = deps.len();
+    deps.retain(|e, _| keep.contains(e));
+    log::info!("Auditing {} of {} ebuilds", deps.len(), before);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::add_ebuild;
+
+    fn example() -> EbuildDeps {
+        let deps = EbuildDeps::new();
+        add_ebuild(&deps, "a/b/b-1.2.ebuild", &[], "amd64 ~arm64");
+        add_ebuild(&deps, "a/b/b-1.10.ebuild", &[], "~amd64 ~arm64");
+        add_ebuild(&deps, "a/b/b-9999.ebuild", &[], "");
+        add_ebuild(&deps, "c/d/d-0.1.ebuild", &[], "~amd64");
+        deps
+    }
+
+    fn paths(deps: &EbuildDeps) -> Vec<String> {
+        let mut paths = deps
+            .iter()
+            .map(|e| e.key().path.clone())
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn newest() {
+        let deps = example();
+        collapse(&deps, false);
+        assert_eq!(vec!["a/b/b-1.10.ebuild", "c/d/d-0.1.ebuild"], paths(&deps));
+        let deps = example();
+        collapse(&deps, true);
+        assert_eq!(vec!["a/b/b-1.2.ebuild"], paths(&deps));
+    }
+}
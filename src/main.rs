@@ -5,20 +5,172 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+mod advisories;
+mod alert;
+mod bump;
+mod bundle;
+mod cleanup;
+mod diff;
+mod fetch;
+mod fragmentation;
 mod gitrepo;
+mod graph;
+mod index;
+mod latest;
+mod msrv;
+mod native;
+mod osv;
 mod overlays;
+mod platform;
+mod policy;
+mod provenance;
 mod re;
+mod report;
+mod suppress;
+mod target;
+#[cfg(test)]
+mod testing;
+mod timeline;
+mod toolchain;
+mod upgrade;
 
 const GENTOO_META_REPO_ORIGIN: &str = "https://github.com/gentoo/api-gentoo-org/";
 const GENTO_META_REPO_REPO_LIST: &str = "files/overlays/repositories.xml";
+// Names for selecting commits with --commit, overlays go by their own name
+const META_REPO: &str = "meta";
+const CRATES_IO_REPO: &str = "crates.io";
+const RUSTSEC_REPO: &str = "rustsec";
 
 #[derive(clap::Clap, Debug)]
 #[clap(about,version,author)]
 struct Opts {
-    #[clap(long, short = 'O')]
-    offline: bool,
     #[clap(long, short = 'd')]
     work_dir: PathBuf,
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(clap::Clap, Debug)]
+enum Cmd {
+    /// Update all repositories in the work dir from the network
+    Fetch,
+    /// Find crates used by ebuilds in the fetched repositories and write their status
+    Analyze(AnalyzeOpts),
+    /// Show a (filtered) view of an analysis result
+    Report(report::ReportOpts),
+    /// Compare two analysis results
+    Diff(diff::DiffOpts),
+    /// Show only advisories published and versions yanked between two runs
+    Alert(alert::AlertOpts),
+    /// Print patches bumping vulnerable or yanked crates in ebuilds to a fixed compatible version
+    Bump(bump::BumpOpts),
+    /// Write all repositories in the work dir to git bundles, for analysis on another machine
+    Export(bundle::BundleOpts),
+    /// Replace the repositories in the work dir with the content of exported bundles
+    Import(bundle::BundleOpts),
+}
+
+#[derive(clap::Clap, Debug)]
+struct AnalyzeOpts {
+    /// Analyse the last commit of every repository at or before this time
+    /// (YYYY-MM-DD, RFC 3339 or @unixtime) instead of HEAD
+    #[clap(long, parse(try_from_str = parse_time))]
+    at: Option<i64>,
+    /// Analyse a specific commit of one repository: <repo>=<commit>, where repo is meta, crates.io,
+    /// rustsec or an overlay name. Takes precedence over --at.
+    #[clap(long = "commit", parse(try_from_str = parse_commit))]
+    commits: Vec<(String, String)>,
+    /// Where to write the result, defaults to status.json in the work dir
+    #[clap(long, short = 'o')]
+    output: Option<PathBuf>,
+    /// Also trace when vulnerable crates entered and left each package in the overlays' history
+    /// and write time-to-fix statistics to timeline.json in the work dir
+    #[clap(long)]
+    timeline: bool,
+    /// Additional directory of advisories in rustsec format, e.g. for embargoed issues
+    #[clap(long = "advisory-db")]
+    advisory_dbs: Vec<PathBuf>,
+    /// Directory or zip file of OSV advisories, e.g. the crates.io export of osv.dev. Entries
+    /// that are aliases of a RustSec advisory are skipped.
+    #[clap(long)]
+    osv: Vec<PathBuf>,
+    /// TOML file of advisories to suppress, defaults to suppressions.toml in the work dir if it
+    /// exists
+    #[clap(long)]
+    suppressions: Option<PathBuf>,
+    /// TOML file with the criteria to rank crates by, defaults to policy.toml in the work dir if
+    /// it exists
+    #[clap(long)]
+    policy: Option<PathBuf>,
+    /// Only audit the newest ebuild of each package in each overlay, and report per package
+    #[clap(long)]
+    latest: bool,
+    /// With --latest, take the newest ebuild with a stable keyword instead
+    #[clap(long, requires = "latest")]
+    stable: bool,
+}
+
+impl Opts {
+    fn rev(&self, repo: &str) -> gitrepo::Rev {
+        match &self.cmd {
+            Cmd::Analyze(opts) => opts.rev(repo),
+            _ => gitrepo::Rev::Head,
+        }
+    }
+    fn repo_path(&self, repo: &str) -> PathBuf {
+        match repo {
+            META_REPO => self.work_dir.join("gentoo"),
+            CRATES_IO_REPO | RUSTSEC_REPO => self.work_dir.join(repo),
+            overlay => self.work_dir.join("overlays").join(overlay),
+        }
+    }
+    fn status_path(&self) -> PathBuf {
+        self.work_dir.join("status.json")
+    }
+    fn suppressions_path(&self) -> PathBuf {
+        self.work_dir.join("suppressions.toml")
+    }
+    fn policy_path(&self) -> PathBuf {
+        self.work_dir.join("policy.toml")
+    }
+}
+
+impl AnalyzeOpts {
+    fn rev(&self, repo: &str) -> gitrepo::Rev {
+        match self.commits.iter().rev().find(|(r, _)| r == repo) {
+            Some((_, commit)) => gitrepo::Rev::Commit(commit.clone()),
+            None => match self.at {
+                Some(time) => gitrepo::Rev::Before(time),
+                None => gitrepo::Rev::Head,
+            },
+        }
+    }
+    /// Whether some repository is analysed at a past state
+    fn is_historic(&self) -> bool {
+        self.at.is_some() || !self.commits.is_empty()
+    }
+}
+
+fn parse_time(s: &str) -> Result<i64> {
+    if let Some(unix) = s.strip_prefix('@') {
+        return Ok(unix.parse().context("Invalid unix timestamp")?);
+    }
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Ok(time.timestamp());
+    }
+    let day = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .context("Expected YYYY-MM-DD, RFC 3339 time or @unixtime")?;
+    Ok(day.and_hms(23, 59, 59).timestamp())
+}
+
+fn parse_commit(s: &str) -> Result<(String, String)> {
+    let mut split = s.splitn(2, '=');
+    match (split.next(), split.next()) {
+        (Some(repo), Some(commit)) if !repo.is_empty() && !commit.is_empty() => {
+            Ok((repo.to_string(), commit.to_string()))
+        }
+        _ => anyhow::bail!("Expected <repo>=<commit>"),
+    }
 }
 
 lazy_static::lazy_static! {
@@ -38,7 +190,26 @@ struct Ebuild {
     path: String,
 }
 
-type EbuildDeps = dashmap::DashMap<Ebuild, Vec<DepInfo>>;
+impl Ebuild {
+    /// category/package
+    fn package(&self) -> &str {
+        match self.path.rfind('/') {
+            Some(i) => &self.path[..i],
+            None => &self.path,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct EbuildInfo {
+    deps: Vec<DepInfo>,
+    keywords: Vec<String>,
+    // Oldest Rust the ebuild allows, if it says
+    rust_min: Option<Version>,
+    native: native::Declared,
+}
+
+type EbuildDeps = dashmap::DashMap<Ebuild, EbuildInfo>;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct CrateStatus {
@@ -47,6 +218,47 @@ struct CrateStatus {
     advisories: Vec<AdvisoryMeta>,
     yanked: Option<bool>,
     ebuilds: Vec<Ebuild>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    suppressed: Vec<suppress::Suppressed>,
+    // Only for vulnerable or yanked crates
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    upgrade: Option<upgrade::Upgrade>,
+    // Ranking by the policy, one value per criterion
+    #[serde(default)]
+    score: Vec<i64>,
+    // Only for crates with advisories or yanked: how each ebuild ends up depending on it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    paths: Vec<graph::DepPath>,
+    // Only for crates with advisories or yanked: ebuilds listing it although nothing needs it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unreachable: Vec<Ebuild>,
+    // Only for crates with advisories or yanked: ebuilds needing it only on other platforms
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    foreign: Vec<Ebuild>,
+}
+
+impl CrateStatus {
+    /// Whether an advisory is suppressed, and not expired, for all ebuilds using the crate
+    fn is_suppressed(&self, advisory: &str) -> bool {
+        self.ebuilds.iter().all(|e| {
+            self.suppressed
+                .iter()
+                .filter(|s| s.advisory == advisory && !s.expired)
+                .any(|s| s.ebuilds.contains(e))
+        })
+    }
+    /// Whether no ebuild actually needs the crate
+    fn is_unreachable(&self) -> bool {
+        self.ebuilds.iter().all(|e| self.unreachable.contains(e))
+    }
+    /// Whether the ebuilds that need the crate only do so on other platforms than Linux
+    fn is_foreign(&self) -> bool {
+        !self.is_unreachable()
+            && self
+                .ebuilds
+                .iter()
+                .all(|e| self.unreachable.contains(e) || self.foreign.contains(e))
+    }
 }
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
@@ -55,118 +267,221 @@ struct DepInfo {
     ver: Version,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 struct AdvisoryMeta {
     id: String,
     title: String,
     cvss: Option<cvss::v3::base::Base>,
+    // rustsec, the local advisory directory or OSV source
+    #[serde(default)]
+    source: String,
+    // YYYY-MM-DD
+    #[serde(default)]
+    date: Option<String>,
+    // CVE, GHSA, ...
+    #[serde(default)]
+    aliases: Vec<String>,
+    // unmaintained, unsound, notice, ... None for actual vulnerabilities
+    #[serde(default)]
+    informational: Option<String>,
+    // Version requirements
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    references: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    // Withdrawn, as yanked RustSec advisories are
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    withdrawn: bool,
+    // Platforms the advisory is restricted to, empty for all
+    #[serde(default)]
+    os: Vec<String>,
+    #[serde(default)]
+    arch: Vec<String>,
+    // Restricted to platforms none of the crate's ebuilds is built for
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    irrelevant: bool,
 }
 
 impl AdvisoryMeta {
-    fn from_advisory(a: &rustsec::Advisory) -> Self {
+    fn from_advisory(source: &str, a: &rustsec::Advisory) -> Self {
+        let m = &a.metadata;
         AdvisoryMeta {
-            id: a.metadata.id.as_str().to_string(),
-            title: a.metadata.title.to_string(),
-            cvss: a.metadata.cvss.clone(),
+            id: m.id.as_str().to_string(),
+            title: m.title.to_string(),
+            cvss: m.cvss.clone(),
+            source: source.to_string(),
+            date: Some(m.date.as_str().to_string()),
+            aliases: m.aliases.iter().map(|i| i.as_str().to_string()).collect(),
+            informational: m.informational.as_ref().map(|i| i.as_str().to_string()),
+            patched: a.versions.patched.iter().map(|r| r.to_string()).collect(),
+            unaffected: a
+                .versions
+                .unaffected
+                .iter()
+                .map(|r| r.to_string())
+                .collect(),
+            url: m.url.as_ref().map(|u| u.to_string()),
+            references: m.references.iter().map(|u| u.to_string()).collect(),
+            categories: m.categories.iter().map(|c| c.name().to_string()).collect(),
+            keywords: m.keywords.iter().map(|k| k.as_str().to_string()).collect(),
+            withdrawn: m.yanked,
+            os: a
+                .affected
+                .iter()
+                .flat_map(|af| &af.os)
+                .map(|os| os.as_str().to_string())
+                .collect(),
+            arch: a
+                .affected
+                .iter()
+                .flat_map(|af| &af.arch)
+                .map(|arch| arch.as_str().to_string())
+                .collect(),
+            irrelevant: false,
         }
     }
+
+    /// As opposed to an informational notice about an unmaintained or unsound crate
+    fn is_vulnerability(&self) -> bool {
+        self.informational.is_none()
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct Output {
+    #[serde(default)]
+    provenance: Option<provenance::Provenance>,
+    // Only the newest ebuild of each package was audited
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    latest: bool,
+    status: Vec<CrateStatus>,
+    // dev-lang/rust(-bin) ebuilds affected by advisories against the toolchain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    toolchains: Vec<toolchain::ToolchainStatus>,
+    // Ebuilds with crates needing a newer Rust than the ebuild declares
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    msrv: Vec<msrv::MsrvIssue>,
+    // Ebuilds whose CRATES lack dependencies of listed crates
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    incomplete: Vec<graph::Incomplete>,
+    // Listed crates nothing depends on, per ebuild
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unreachable: Vec<graph::Unreachable>,
+    // Ebuilds whose own crate isn't listed, so unneeded crates can't be told
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unchecked: Vec<Ebuild>,
+    // Ebuilds building bundled native libraries or lacking the system ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    native: Vec<native::NativeIssue>,
+    // Crates used in several versions across all ebuilds
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fragmentation: Vec<fragmentation::Fragmentation>,
+    // Vulnerable ebuilds that a fixed newer version of the package makes unnecessary
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    superseded: Vec<cleanup::Superseded>,
+    // Packages with vulnerabilities in every version
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    affected: Vec<cleanup::Affected>,
 }
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
     log::trace!("Opts: {:#?}", *OPTS);
 
-    let overlays = fgo()?;
+    match &OPTS.cmd {
+        Cmd::Fetch => fetch::fetch(),
+        Cmd::Analyze(opts) => analyze(opts),
+        Cmd::Report(opts) => report::report(opts),
+        Cmd::Diff(opts) => diff::diff(opts),
+        Cmd::Alert(opts) => alert::alert(opts),
+        Cmd::Bump(opts) => bump::bump(opts),
+        Cmd::Export(opts) => bundle::export(opts),
+        Cmd::Import(opts) => bundle::import(opts),
+    }
+}
+
+fn analyze(opts: &AnalyzeOpts) -> Result<()> {
+    let started = chrono::Utc::now();
+    let inputs = provenance::Recorder::default();
+    let overlays = fgo(&inputs)?;
+    let overlay_names = overlays.iter().map(|o| o.name.clone()).collect::<Vec<_>>();
     let pool = rayon::ThreadPoolBuilder::new().build().unwrap();
 
     let mut yanks = Err(anyhow::anyhow!("crates.io not retrieved"));
     let mut rustsec_get = Err(anyhow::anyhow!("Rustsec repo not retrieved"));
-    let sec_db_path = OPTS.work_dir.join("rustsec");
+    let sec_db_path = OPTS.repo_path(RUSTSEC_REPO);
     let gentoo_overlay_status =
         AtomicCell::new(Err(anyhow::anyhow!("gentoo overlay not processed")));
     let deps = EbuildDeps::new();
+    let toolchains = toolchain::Toolchains::new();
 
     pool.scope(|scope| {
         scope.spawn(|_| {
             rustsec_get = (|| -> Result<_> {
                 let repo = gitrepo::RepoRepo::on_checkout(&sec_db_path)?;
-                repo.up_or_head(rustsec::repository::git::DEFAULT_URL, OPTS.offline)?;
-                Ok(())
-            })().context("Get rustsec");
+                let rev = OPTS.rev(RUSTSEC_REPO);
+                let commit = repo.pick(&rev)?;
+                inputs.repository(RUSTSEC_REPO, &repo, &commit);
+                if rev == gitrepo::Rev::Head {
+                    return Ok(None);
+                }
+                // The rustsec crate only loads from the file system, so past states need a copy
+                let historic = OPTS
+                    .work_dir
+                    .join("history")
+                    .join(RUSTSEC_REPO)
+                    .join(commit.id().to_string());
+                if !historic.exists() {
+                    let tmp = historic.with_extension("tmp");
+                    if tmp.exists() {
+                        std::fs::remove_dir_all(&tmp)?;
+                    }
+                    std::fs::create_dir_all(&tmp)?;
+                    repo.extract(&commit.tree()?, &tmp)?;
+                    std::fs::rename(&tmp, &historic)?;
+                }
+                Ok(Some(historic))
+            })()
+            .context("Get rustsec");
         });
         scope.spawn(|_| {
-            yanks = cio();
+            yanks = cio(&inputs);
         });
         for overlay in overlays {
             scope.spawn(|_scope| {
                 let overlay = overlay;
                 let act = (|| -> Result<()> {
-                    fn source_goodness(url: &str) -> i8 {
-                        if url.starts_with("git://") {
-                            -2
-                        } else if url.starts_with("https://") {
-                            -1
-                        } else if url.starts_with("git@") {
-                            1
-                        } else if url.starts_with("git+ssh://") {
-                            2
-                        } else if url.starts_with("ssh+git://") {
-                            2
-                        } else {
-                            0
-                        }
-                    }
-                    let mut sources = overlay
-                        .sources
-                        .iter()
-                        .filter(|s| s.typ == overlays::SourceType::Git)
-                        .collect::<Vec<_>>();
-                    if sources.is_empty() {
-                        log::info!("No git source for {}, ignoring", overlay.name);
-                        return Ok(());
-                    };
-                    sources.sort_by_key(|s| source_goodness(&s.url));
-                    let repopath = &OPTS.work_dir.join("overlays").join(&overlay.name);
-                    if OPTS.offline && !repopath.exists() {
-                        log::info!("Overlay {} not cloned yet, skipping in offline mode", overlay.name);
+                    let repopath = &OPTS.repo_path(&overlay.name);
+                    if !repopath.exists() {
+                        log::info!("Overlay {} not fetched yet, skipping", overlay.name);
                         return Ok(());
                     }
                     let repo = gitrepo::RepoRepo::on(repopath)?;
-
-                    let mut head = repo
-                        .repo()
-                        .head()
-                        .context("Fetch failed, use previous HEAD");
-                    if !OPTS.offline {
-                        for source in sources {
-                            match repo.up_or_head(&source.url, OPTS.offline) {
-                                h @ Ok(_) => {
-                                    head = h;
-                                    break;
-                                }
-                                Err(e) => {
-                                    log::error!(
-                                        "Failed to update overlay {} with source {}:{}",
-                                        overlay.name,
-                                        source.url,
-                                        format_chain(&e),
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    if OPTS.offline && head.is_err() {
-                        log::info!("Overlay {}'s repository exists but has no HEAD, skipping in offline mode", overlay.name);
+                    if repo.repo().head().is_err() {
+                        log::info!(
+                            "Overlay {}'s repository exists but has no HEAD, skipping",
+                            overlay.name
+                        );
                         return Ok(());
                     }
-                    let head = head?;
 
-                    head.peel_to_tree()?
+                    let commit = repo.pick(&OPTS.rev(&overlay.name))?;
+                    inputs.overlay(&overlay.name, &repo, &commit);
+                    commit
+                        .tree()?
                         .walk(
                             git2::TreeWalkMode::PreOrder,
-                            find_cargo_ebuilds(repo.repo(), &overlay.name, &deps),
+                            find_cargo_ebuilds(repo.repo(), &overlay.name, &deps, &toolchains),
                         )
-                        .context("Search HEAD tree")?;
+                        .context("Search tree")?;
 
                     Ok(())
                 })();
@@ -185,46 +500,87 @@ fn main() -> Result<()> {
 
     let yanks = yanks?;
     gentoo_overlay_status.swap(Ok(()))?;
-    rustsec_get?;
-    let sec_db = rustsec::repository::git::Repository::open(&sec_db_path).context(format!(
-        "Failed to open rustsec db at {}",
-        sec_db_path.to_string_lossy()
-    ))?;
-    anyhow::ensure!(
-        sec_db.latest_commit()?.is_fresh(),
-        "Rustsec database is stale"
-    );
-    let sec_db = rustsec::database::Database::load_from_repo(&sec_db)
-        .context("Load rustsec DB from repo")?;
+    // The versions across the tree, not what's left to audit
+    let fragmentation = fragmentation::stats(&deps);
+    if opts.latest {
+        latest::collapse(&deps, opts.stable);
+    }
+    let sec_db = match rustsec_get? {
+        None => {
+            let sec_db =
+                rustsec::repository::git::Repository::open(&sec_db_path).context(format!(
+                    "Failed to open rustsec db at {}",
+                    sec_db_path.to_string_lossy()
+                ))?;
+            anyhow::ensure!(
+                sec_db.latest_commit()?.is_fresh(),
+                "Rustsec database is stale"
+            );
+            rustsec::database::Database::load_from_repo(&sec_db)
+                .context("Load rustsec DB from repo")?
+        }
+        Some(historic) => rustsec::database::Database::open(&historic).context(format!(
+            "Load rustsec DB from {}",
+            historic.to_string_lossy()
+        ))?,
+    };
     let sec_db_info = rustsec::report::DatabaseInfo::new(&sec_db);
     log::info!("rustsec: {:?}", sec_db_info);
     anyhow::ensure!(
         sec_db_info.advisory_count > 0,
         "0 advisories found. Sounds  wrong."
     );
+    let mut sec_db = advisories::Advisories::new(sec_db);
+    for dir in &opts.advisory_dbs {
+        sec_db.add_local(dir)?;
+    }
+    for path in &opts.osv {
+        sec_db.add_osv(path)?;
+    }
+    let suppressions = match &opts.suppressions {
+        Some(path) => Some(suppress::Suppressions::load(path)?),
+        None if OPTS.suppressions_path().exists() => {
+            Some(suppress::Suppressions::load(&OPTS.suppressions_path())?)
+        }
+        None => None,
+    };
+    let policy = match &opts.policy {
+        Some(path) => policy::Policy::load(path)?,
+        None if OPTS.policy_path().exists() => policy::Policy::load(&OPTS.policy_path())?,
+        None => policy::Policy::default(),
+    };
 
     let mut crates = HashMap::new();
     for e in &deps {
-        for dep in e.value() {
+        for dep in &e.value().deps {
             crates
                 .entry(dep.clone())
                 .or_insert_with(|| {
-                    let sec_query = rustsec::database::Query::crate_scope()
-                        .package_version(dep.name.clone(), dep.ver.clone());
                     let advisories = sec_db
-                        .query(&sec_query)
+                        .query(dep)
                         .into_iter()
-                        .map(AdvisoryMeta::from_advisory)
-                        .collect();
-                    let yanked = yanks
-                        .get(&dep.name)
-                        .and_then(|vs| vs.get(&dep.ver))
-                        .map(|v| *v);
+                        .map(|f| f.meta())
+                        .collect::<Vec<_>>();
+                    let versions = yanks.get(&dep.name);
+                    let yanked = versions.and_then(|vs| vs.get(&dep.ver)).copied();
+                    let vulnerable = advisories.iter().any(|a| a.is_vulnerability());
+                    let upgrade = match (versions, vulnerable, yanked) {
+                        (Some(versions), true, _) | (Some(versions), _, Some(true)) => {
+                            Some(upgrade::find(dep, versions, &sec_db))
+                        }
+                        _ => None,
+                    };
                     CrateStatus {
                         id: dep.clone(),
                         ebuilds: vec![],
                         yanked,
                         advisories,
+                        suppressed: vec![],
+                        upgrade,
+                        score: vec![],
+                        paths: vec![],
+                        unreachable: vec![],
+                        foreign: vec![],
                     }
                 })
                 .ebuilds
@@ -232,36 +588,153 @@ fn main() -> Result<()> {
         }
     }
     let mut crates = crates.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
-    std::mem::drop(deps);
-
-    crates.sort_by_cached_key(|e| {
-        let used = e.ebuilds.len();
-        let gentoo_used = e.ebuilds.iter().filter(|e| e.overlay == "gentoo").count();
-        let score = e
-            .advisories
+    for status in &mut crates {
+        let keywords = status
+            .ebuilds
             .iter()
-            .filter_map(|v| v.cvss.as_ref().map(|v| (v.score().value() * 1000.0) as i64))
-            .max()
-            .unwrap_or(i64::MIN);
-        let prio = match e.advisories.is_empty() {
-            false => 3,
-            true => match e.yanked {
-                Some(true) => 2,
-                None => 1,
-                Some(false) => 0,
-            },
-        };
-        std::cmp::Reverse((prio, gentoo_used, score, used))
+            .filter_map(|e| deps.get(e))
+            .flat_map(|i| i.keywords.clone())
+            .collect::<Vec<_>>();
+        let arches = platform::arches(&keywords);
+        for a in &mut status.advisories {
+            a.irrelevant = !platform::relevant(a, &arches);
+        }
+    }
+    let index = index::load(crates.iter().map(|c| &c.id.name)).context("Read crates.io index")?;
+    let msrv = msrv::check(
+        &deps,
+        &index,
+        toolchains.iter().map(|t| t.value().clone()).min().as_ref(),
+    );
+    let flagged = crates
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| !c.advisories.is_empty() || c.yanked == Some(true))
+        .map(|(i, c)| (c.id.clone(), i))
+        .collect::<HashMap<_, _>>();
+    let mut incomplete = vec![];
+    let mut unreachable = vec![];
+    let mut unchecked = vec![];
+    let mut native = vec![];
+    for e in &deps {
+        let info = e.value();
+        let root = re::split_pkgver(&e.key().path).map(|(pn, _)| pn);
+        let arches = platform::arches(&info.keywords);
+        let mut graph = graph::build(&info.deps, &index, root, &arches);
+        if !graph.unsatisfied.is_empty() {
+            incomplete.push(graph::Incomplete {
+                ebuild: e.key().clone(),
+                unsatisfied: std::mem::take(&mut graph.unsatisfied),
+            });
+        }
+        if !graph.own_root {
+            unchecked.push(e.key().clone());
+        }
+        let reachable = graph.reachable(false);
+        let on_linux = graph.reachable(true);
+        let mut stale = vec![];
+        for (target, dep) in info.deps.iter().enumerate() {
+            if !reachable[target] && graph.own_root {
+                stale.push(dep.clone());
+                if let Some(&i) = flagged.get(dep) {
+                    crates[i].unreachable.push(e.key().clone());
+                }
+            } else if !on_linux[target] {
+                if let Some(&i) = flagged.get(dep) {
+                    crates[i].foreign.push(e.key().clone());
+                }
+            }
+            if let Some(&i) = flagged.get(dep) {
+                for path in graph.paths_to(target) {
+                    crates[i].paths.push(graph::DepPath {
+                        ebuild: e.key().clone(),
+                        path: path.into_iter().map(|n| graph.nodes[n].clone()).collect(),
+                    });
+                }
+            }
+        }
+        if !stale.is_empty() {
+            unreachable.push(graph::Unreachable {
+                ebuild: e.key().clone(),
+                crates: stale,
+            });
+        }
+        native.extend(native::check(
+            e.key(),
+            &info.native,
+            &graph,
+            &index,
+            &on_linux,
+        ));
+    }
+    incomplete.sort_by(|a, b| {
+        (&a.ebuild.overlay, &a.ebuild.path).cmp(&(&b.ebuild.overlay, &b.ebuild.path))
+    });
+    unreachable.sort_by(|a, b| {
+        (&a.ebuild.overlay, &a.ebuild.path).cmp(&(&b.ebuild.overlay, &b.ebuild.path))
     });
+    unchecked.sort_by(|a, b| (&a.overlay, &a.path).cmp(&(&b.overlay, &b.path)));
+    native.sort_by(|a, b| {
+        (&a.ebuild.overlay, &a.ebuild.path).cmp(&(&b.ebuild.overlay, &b.ebuild.path))
+    });
+    // With only the newest ebuilds audited, there are no older ones to clean up
+    let ebuilds = match opts.latest {
+        true => vec![],
+        false => deps.iter().map(|e| e.key().clone()).collect::<Vec<_>>(),
+    };
+    std::mem::drop(deps);
+    for status in &mut crates {
+        // Needed somewhere, but never on Linux
+        if status.is_foreign() {
+            status
+                .advisories
+                .iter_mut()
+                .for_each(|a| a.irrelevant = true);
+        }
+    }
+    if let Some(suppressions) = &suppressions {
+        crates.iter_mut().for_each(|c| suppressions.apply(c));
+    }
+    let (superseded, affected) = cleanup::find(ebuilds, &crates);
 
-    #[derive(serde::Serialize)]
-    struct Output {
-        status: Vec<CrateStatus>,
+    for status in &mut crates {
+        status.score = policy.score(status);
     }
-    let outpath = OPTS.work_dir.join("status.json");
+    crates.sort_by(|a, b| b.score.cmp(&a.score));
+
+    let outpath = opts.output.clone().unwrap_or_else(|| OPTS.status_path());
     log::debug!("Writing result to {}", outpath.to_string_lossy());
-    let file = std::fs::File::create(outpath).context("Open output file")?;
-    serde_json::to_writer_pretty(file, &Output { status: crates }).context("Write output")?;
+    let file = std::fs::File::create(&outpath).context("Open output file")?;
+    let output = Output {
+        provenance: Some(inputs.finish(started)),
+        latest: opts.latest,
+        status: crates,
+        toolchains: toolchain::audit(toolchains, &sec_db),
+        msrv,
+        incomplete,
+        unreachable,
+        unchecked,
+        native,
+        fragmentation,
+        superseded,
+        affected,
+    };
+    serde_json::to_writer_pretty(file, &output).context("Write output")?;
+    // Past states, or only the newest ebuilds, would look like changes to diff and alert, which
+    // take the latest runs
+    if !opts.is_historic() && !opts.latest {
+        std::fs::create_dir_all(diff::runs_dir())?;
+        let run = diff::runs_dir().join(format!("{}.json", started.format("%Y%m%dT%H%M%SZ")));
+        std::fs::copy(&outpath, &run).context("Keep copy of run")?;
+    }
+
+    if opts.timeline {
+        let timeline = timeline::trace(&overlay_names, &sec_db);
+        let outpath = OPTS.work_dir.join("timeline.json");
+        log::debug!("Writing timeline to {}", outpath.to_string_lossy());
+        let file = std::fs::File::create(outpath).context("Open timeline file")?;
+        serde_json::to_writer_pretty(file, &timeline).context("Write timeline")?;
+    }
 
     Ok(())
 }
@@ -270,17 +743,22 @@ fn find_cargo_ebuilds<'a>(
     repo: &'a git2::Repository,
     overlay: &'a str,
     ret: &'a EbuildDeps,
+    toolchains: &'a toolchain::Toolchains,
 ) -> impl 'a + FnMut(&str, &git2::TreeEntry<'_>) -> git2::TreeWalkResult {
     move |root, entry| {
         if Some(git2::ObjectType::Blob) == entry.kind() {
             if let Some(name) = entry.name() {
                 if name.ends_with(".ebuild") {
+                    let path = format!("{}{}", root, name);
+                    if let Some(ver) = toolchain::version(&path) {
+                        let overlay = overlay.to_string();
+                        toolchains.insert(Ebuild { overlay, path }, ver);
+                        return git2::TreeWalkResult::Ok;
+                    }
                     let content = entry.to_object(repo).unwrap();
                     let content = content.as_blob().expect("Object blob").content();
                     let content = String::from_utf8_lossy(content);
-                    if content.contains("cargo_crate_uris ")
-                        || re::USES_CARGO_ECLASS.is_match(&content)
-                    {
+                    if uses_cargo(&content) {
                         parse(overlay, format!("{}{}", root, name), &content, ret);
                     }
                 }
@@ -291,6 +769,27 @@ fn find_cargo_ebuilds<'a>(
 }
 
 fn parse(overlay: &str, path: String, content: &str, ret: &EbuildDeps) {
+    if let Some(deps) = parse_crates(overlay, &path, content) {
+        let keywords = re::KEYWORDS
+            .captures(content)
+            .map(|capt| capt[1].split_whitespace().map(String::from).collect())
+            .unwrap_or_default();
+        let overlay = overlay.to_string();
+        let info = EbuildInfo {
+            deps,
+            keywords,
+            rust_min: msrv::declared(content),
+            native: native::declared(content),
+        };
+        ret.insert(Ebuild { overlay, path }, info);
+    }
+}
+
+fn uses_cargo(content: &str) -> bool {
+    content.contains("cargo_crate_uris ") || re::USES_CARGO_ECLASS.is_match(content)
+}
+
+fn parse_crates(overlay: &str, path: &str, content: &str) -> Option<Vec<DepInfo>> {
     if !content.contains(r"$(cargo_crate_uris ${CRATES})")
         && !content.contains(r"$(cargo_crate_uris $CRATES)")
     {
@@ -306,12 +805,12 @@ fn parse(overlay: &str, path: String, content: &str, ret: &EbuildDeps) {
                 overlay,
                 path,
             );
-            return;
+            return None;
         }
     }
     if let Some(capt) = re::CRATES.captures(content) {
         let crates = &capt[1];
-        let crates = match re::split_pkgver(&path) {
+        let crates = match re::split_pkgver(path) {
             Some((pn, pv)) => crates
                 .replace("${P}", &format!("{}-{}", pn, pv))
                 .replace("${PV}", pv)
@@ -342,14 +841,14 @@ fn parse(overlay: &str, path: String, content: &str, ret: &EbuildDeps) {
             })
             .collect::<Vec<_>>();
         log::debug!("{}::{}: deps: {:#?}", overlay, path, res);
-        let overlay = overlay.to_string();
-        ret.insert(Ebuild { overlay, path }, res);
+        Some(res)
     } else {
         log::warn!(
             "{}::{}: Could not get declaration of CRATES list",
             overlay,
             path,
         );
+        None
     }
 }
 
@@ -362,11 +861,12 @@ fn cratespec_to_depinfo(spec_str: &str) -> Result<DepInfo> {
     Ok(DepInfo { name, ver })
 }
 
-fn fgo() -> Result<Vec<overlays::Overlay>> {
+fn fgo(inputs: &provenance::Recorder) -> Result<Vec<overlays::Overlay>> {
     Ok((|| -> Result<_> {
-        let gentoo_meta = gitrepo::RepoRepo::on(&OPTS.work_dir.join("gentoo"))?;
-        let head = gentoo_meta.up_or_head(GENTOO_META_REPO_ORIGIN, OPTS.offline)?;
-        let tree = head.peel_to_tree()?;
+        let gentoo_meta = gitrepo::RepoRepo::on(&OPTS.repo_path(META_REPO))?;
+        let commit = gentoo_meta.pick(&OPTS.rev(META_REPO))?;
+        inputs.repository(META_REPO, &gentoo_meta, &commit);
+        let tree = commit.tree()?;
         let ret = overlays::parse(
             tree.get_path(&Path::new(GENTO_META_REPO_REPO_LIST))?
                 .to_object(gentoo_meta.repo())?
@@ -380,16 +880,18 @@ fn fgo() -> Result<Vec<overlays::Overlay>> {
     .context("Obtain gentoo overlay list")?)
 }
 
-fn cio() -> Result<YankingStatus> {
+fn cio(inputs: &provenance::Recorder) -> Result<YankingStatus> {
     let mut ret = HashMap::new();
-    let repo = gitrepo::RepoRepo::on(&OPTS.work_dir.join("crates.io"))?;
-    let head = repo.up_or_head(cargo::sources::registry::CRATES_IO_INDEX, OPTS.offline)?;
-    head.peel_to_tree()?
+    let repo = gitrepo::RepoRepo::on(&OPTS.repo_path(CRATES_IO_REPO))?;
+    let commit = repo.pick(&OPTS.rev(CRATES_IO_REPO))?;
+    inputs.repository(CRATES_IO_REPO, &repo, &commit);
+    commit
+        .tree()?
         .walk(
             git2::TreeWalkMode::PreOrder,
             list_crates(repo.repo(), &mut ret),
         )
-        .context("List crates in crates.io repo HEAD tree")?;
+        .context("List crates in crates.io repo tree")?;
     Ok(ret)
 }
 
@@ -400,6 +902,42 @@ struct RegistryPackage {
     name: String,
     vers: String,
     yanked: bool,
+    #[serde(default)]
+    deps: Vec<RegistryDependency>,
+    // MSRV, may lack the patch level
+    #[serde(default)]
+    rust_version: Option<String>,
+    // Native library the crate links against
+    #[serde(default)]
+    links: Option<String>,
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+    // Features using newer syntax, dep: and ?/
+    #[serde(default)]
+    features2: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct RegistryDependency {
+    // As the depending crate refers to it, see package
+    name: String,
+    req: String,
+    #[serde(default)]
+    optional: bool,
+    // normal, build or dev, None meaning normal
+    #[serde(default)]
+    kind: Option<String>,
+    // The actual crate name, if renamed
+    #[serde(default)]
+    package: Option<String>,
+    // cfg expression or target triple for platform-specific dependencies
+    #[serde(default)]
+    target: Option<String>,
+    #[serde(default)]
+    features: Vec<String>,
+    // None meaning true
+    #[serde(default)]
+    default_features: Option<bool>,
 }
 
 type YankingStatus = HashMap<Name, HashMap<Version, bool>>;
@@ -416,19 +954,7 @@ fn list_crates<'a>(
                 }
                 let content = entry.to_object(repo).unwrap();
                 let content = content.as_blob().expect("Object blob").content();
-                use std::io::BufRead;
-                for (i, line) in content.lines().enumerate() {
-                    match parse_spec(folder, name, line, ret) {
-                        Ok(()) => (),
-                        Err(e) => log::error!(
-                            "Cannot parse crate info for {}{}:{}: {}",
-                            folder,
-                            name,
-                            i + 1,
-                            e
-                        ),
-                    }
-                }
+                parse_index_file(folder, name, content, ret);
             } else {
                 log::error!("Strange object without name in {}", folder);
             }
@@ -437,6 +963,22 @@ fn list_crates<'a>(
     }
 }
 
+fn parse_index_file(folder: &str, filename: &str, content: &[u8], ret: &mut YankingStatus) {
+    use std::io::BufRead;
+    for (i, line) in content.lines().enumerate() {
+        match parse_spec(folder, filename, line, ret) {
+            Ok(()) => (),
+            Err(e) => log::error!(
+                "Cannot parse crate info for {}{}:{}: {}",
+                folder,
+                filename,
+                i + 1,
+                e
+            ),
+        }
+    }
+}
+
 fn parse_spec(
     folder: &str,
     filename: &str,
@@ -0,0 +1,132 @@
+//! Ebuilds that allow an older Rust than their crates need: the minimum supported Rust version
+//! (rust_version in the index) of some crate is above what the ebuild declares as minimum.
+//! Ebuilds declaring no minimum allow any Rust in the tree, so they are held to the oldest
+//! toolchain ebuild found.
+
+use crate::{index, re, DepInfo, Ebuild, EbuildDeps};
+use rustsec::package::Version;
+use std::str::FromStr;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MsrvIssue {
+    pub ebuild: Ebuild,
+    // From RUST_MIN_VER or a >=virtual/rust-X dependency, else the oldest toolchain
+    pub declared: Version,
+    // Whether the ebuild declares no minimum at all
+    #[serde(default)]
+    pub undeclared: bool,
+    pub required: Version,
+    // The crates that need more than declared
+    pub crates: Vec<DepInfo>,
+}
+
+/// Rust versions are often given without patch level
+fn rust_version(s: &str) -> Option<Version> {
+    match s.split('.').count() {
+        2 => Version::from_str(&format!("{}.0", s)).ok(),
+        _ => Version::from_str(s).ok(),
+    }
+}
+
+/// The minimum Rust an ebuild declares, if any
+pub fn declared(content: &str) -> Option<Version> {
+    re::RUST_MIN
+        .captures_iter(content)
+        .filter_map(|capt| rust_version(&capt[1]))
+        .max()
+}
+
+/// Ebuilds with crates needing more than their minimum Rust, or than `oldest` if they declare none
+pub fn check(
+    deps: &EbuildDeps,
+    index: &index::Entries,
+    oldest: Option<&Version>,
+) -> Vec<MsrvIssue> {
+    let mut ret = deps
+        .iter()
+        .filter_map(|e| {
+            let undeclared = e.value().rust_min.is_none();
+            let declared = e.value().rust_min.as_ref().or(oldest)?.clone();
+            let mut crates = e
+                .value()
+                .deps
+                .iter()
+                .filter_map(|dep| {
+                    let entry = index.get(&dep.name)?.get(&dep.ver)?;
+                    let msrv = rust_version(entry.rust_version.as_ref()?)?;
+                    Some((msrv, dep.clone()))
+                })
+                .filter(|(msrv, _)| msrv > &declared)
+                .collect::<Vec<_>>();
+            let required = crates.iter().map(|(msrv, _)| msrv.clone()).max()?;
+            crates.sort_by(|a, b| b.0.cmp(&a.0));
+            Some(MsrvIssue {
+                ebuild: e.key().clone(),
+                declared,
+                undeclared,
+                required,
+                crates: crates.into_iter().map(|(_, dep)| dep).collect(),
+            })
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| {
+        (&a.ebuild.overlay, &a.ebuild.path).cmp(&(&b.ebuild.overlay, &b.ebuild.path))
+    });
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{add_ebuild, add_package, dep, ebuild};
+
+    #[test]
+    fn declarations() {
+        let v = |s: &str| Some(Version::from_str(s).unwrap());
+        assert_eq!(v("1.53.0"), declared("\nRUST_MIN_VER=\"1.53\"\n"));
+        assert_eq!(
+            v("1.56.1"),
+            declared("BDEPEND=\">=virtual/rust-1.47\n\t>=dev-lang/rust-bin-1.56.1\"")
+        );
+        assert_eq!(v("1.70.0"), declared("RUST_MIN_VER=1.70\n"));
+        assert_eq!(None, declared("BDEPEND=\"virtual/rust\""));
+    }
+
+    #[test]
+    fn undeclared() {
+        let syn = dep("syn", "2.0.0");
+        let mut index = index::Entries::new();
+        add_package(
+            &mut index,
+            &syn,
+            serde_json::json!({"rust_version": "1.56"}),
+        );
+        let deps = EbuildDeps::new();
+        for (path, rust_min) in &[
+            ("a/a/a-1.ebuild", Some("1.60")),
+            ("b/b/b-1.ebuild", Some("1.53")),
+            ("c/c/c-1.ebuild", None),
+        ] {
+            add_ebuild(&deps, path, &[syn.clone()], "");
+            deps.get_mut(&ebuild("gentoo", path)).unwrap().rust_min =
+                rust_min.and_then(rust_version);
+        }
+        let issues = |oldest: Option<&str>| {
+            check(&deps, &index, oldest.and_then(rust_version).as_ref())
+                .into_iter()
+                .map(|m| (m.ebuild.path, m.declared.to_string(), m.undeclared))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![("b/b/b-1.ebuild".to_string(), "1.53.0".to_string(), false)],
+            issues(None)
+        );
+        assert_eq!(
+            vec![
+                ("b/b/b-1.ebuild".to_string(), "1.53.0".to_string(), false),
+                ("c/c/c-1.ebuild".to_string(), "1.54.0".to_string(), true),
+            ],
+            issues(Some("1.54.0"))
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! Native code in -sys crates. Each either links against a system library, which the ebuild
+//! should then depend on, or builds a bundled copy, which Gentoo policy disfavours. What a crate
+//! does depends on the features enabled for it, on source crates like openssl-src being present
+//! and, for some, on environment variables the ebuild can set. Features are followed through the
+//! index's feature tables as far as the CRATES list tells; the package's own features are unknown.
+
+use crate::{graph, index, re, DepInfo, Ebuild};
+use std::collections::HashSet;
+
+struct Sys {
+    krate: &'static str,
+    package: &'static str,
+    // Features that build the bundled copy
+    features: &'static [&'static str],
+    // Crates containing the library's source, only listed when building it
+    source: Option<&'static str>,
+    // For crates that prefer their own copy: the variable that makes them use the system library
+    system_env: Option<&'static str>,
+}
+
+const KNOWN: &[Sys] = &[
+    Sys {
+        krate: "openssl-sys",
+        package: "dev-libs/openssl",
+        features: &["vendored"],
+        source: Some("openssl-src"),
+        system_env: None,
+    },
+    Sys {
+        krate: "libgit2-sys",
+        package: "dev-libs/libgit2",
+        features: &[],
+        source: None,
+        system_env: Some("LIBGIT2_SYS_USE_PKG_CONFIG"),
+    },
+    Sys {
+        krate: "libssh2-sys",
+        package: "net-libs/libssh2",
+        features: &[],
+        source: None,
+        system_env: Some("LIBSSH2_SYS_USE_PKG_CONFIG"),
+    },
+    Sys {
+        krate: "libsqlite3-sys",
+        package: "dev-db/sqlite",
+        features: &["bundled", "bundled-sqlcipher", "bundled-windows"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "zstd-sys",
+        package: "app-arch/zstd",
+        features: &[],
+        source: None,
+        system_env: Some("ZSTD_SYS_USE_PKG_CONFIG"),
+    },
+    Sys {
+        krate: "libz-sys",
+        package: "sys-libs/zlib",
+        features: &["static"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "bzip2-sys",
+        package: "app-arch/bzip2",
+        features: &["static"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "lzma-sys",
+        package: "app-arch/xz-utils",
+        features: &["static"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "curl-sys",
+        package: "net-misc/curl",
+        features: &["static-curl"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "onig_sys",
+        package: "dev-libs/oniguruma",
+        features: &[],
+        source: None,
+        system_env: Some("RUSTONIG_SYSTEM_LIBONIG"),
+    },
+    Sys {
+        krate: "pcre2-sys",
+        package: "dev-libs/libpcre2",
+        features: &["static"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "libdbus-sys",
+        package: "sys-apps/dbus",
+        features: &["vendored"],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "tikv-jemalloc-sys",
+        package: "dev-libs/jemalloc",
+        features: &[],
+        source: None,
+        system_env: Some("JEMALLOC_OVERRIDE"),
+    },
+    Sys {
+        krate: "libudev-sys",
+        package: "virtual/libudev",
+        features: &[],
+        source: None,
+        system_env: None,
+    },
+    Sys {
+        krate: "alsa-sys",
+        package: "media-libs/alsa-lib",
+        features: &[],
+        source: None,
+        system_env: None,
+    },
+];
+
+// Usual names of bundling features, for other crates linking a native library
+const BUNDLING: &[&str] = &["vendored", "bundled", "static"];
+
+/// What an ebuild says about the known native libraries
+#[derive(Debug)]
+pub struct Declared {
+    // Packages of the table the ebuild mentions, usually in its dependencies
+    packages: Vec<&'static str>,
+    // Variables of the table the ebuild sets
+    env: Vec<&'static str>,
+}
+
+pub fn declared(content: &str) -> Declared {
+    let content = content
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let atoms = re::DEPEND_VARS
+        .captures_iter(&content)
+        .filter_map(|capt| capt.get(1))
+        .flat_map(|m| m.as_str().split_whitespace())
+        .collect::<Vec<_>>();
+    Declared {
+        packages: KNOWN
+            .iter()
+            .map(|s| s.package)
+            .filter(|p| atoms.iter().any(|a| is_atom_of(a, p)))
+            .collect(),
+        env: KNOWN
+            .iter()
+            .filter_map(|s| s.system_env)
+            .filter(|v| content.contains(&format!("{}=", v)))
+            .collect(),
+    }
+}
+
+// Whether the atom names the package, and not one whose name merely starts the same
+fn is_atom_of(atom: &str, package: &str) -> bool {
+    let rest = match atom
+        .trim_start_matches(|c| "!<>=~".contains(c))
+        .strip_prefix(package)
+    {
+        Some(rest) => rest,
+        None => return false,
+    };
+    match rest.strip_prefix('-') {
+        Some(ver) => ver.starts_with(|c: char| c.is_ascii_digit()),
+        None => rest.is_empty() || rest.starts_with(|c| c == ':' || c == '['),
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct NativeIssue {
+    pub ebuild: Ebuild,
+    pub findings: Vec<Finding>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Finding {
+    #[serde(rename = "crate")]
+    pub id: DepInfo,
+    // The native library, from the index
+    pub links: Option<String>,
+    pub package: Option<String>,
+    pub problem: Problem,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    // Why it's probably built from the bundled copy
+    Bundled(String),
+    // Links the system library, but the ebuild doesn't depend on it
+    MissingDependency,
+}
+
+/// Features enabled on each crate, as far as the crates depending on it tell
+fn features(graph: &graph::Graph, index: &index::Entries, active: &[bool]) -> Vec<HashSet<String>> {
+    let entry = |n: usize| {
+        let c = &graph.nodes[n];
+        index.get(&c.name).and_then(|vs| vs.get(&c.ver))
+    };
+    let mut ret = vec![HashSet::new(); graph.nodes.len()];
+    let mut queue = graph
+        .roots
+        .iter()
+        .map(|&r| (r, "default".to_string()))
+        .collect::<Vec<_>>();
+    for (from, edges) in graph.edges.iter().enumerate() {
+        let entry = match entry(from) {
+            Some(entry) if active[from] => entry,
+            _ => continue,
+        };
+        for e in edges.iter().filter(|e| e.linux) {
+            let dep = &entry.deps[e.dep];
+            queue.extend(dep.features.iter().map(|f| (e.to, f.clone())));
+            if dep.default_features.unwrap_or(true) {
+                queue.push((e.to, "default".to_string()));
+            }
+        }
+    }
+    while let Some((n, feature)) = queue.pop() {
+        if !ret[n].insert(feature.clone()) {
+            continue;
+        }
+        let entry = match entry(n) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let enables = entry
+            .features
+            .get(&feature)
+            .into_iter()
+            .chain(entry.features2.get(&feature))
+            .flatten();
+        for enabled in enables {
+            if enabled.starts_with("dep:") {
+                continue;
+            }
+            match enabled.find('/') {
+                Some(slash) => {
+                    let (name, f) = (
+                        enabled[..slash].trim_end_matches('?'),
+                        &enabled[slash + 1..],
+                    );
+                    queue.extend(
+                        graph.edges[n]
+                            .iter()
+                            .filter(|e| entry.deps[e.dep].name == name)
+                            .map(|e| (e.to, f.to_string())),
+                    );
+                }
+                None => queue.push((n, enabled.clone())),
+            }
+        }
+    }
+    ret
+}
+
+/// Bundled or undeclared native libraries of the crates an ebuild needs on Linux
+pub fn check(
+    ebuild: &Ebuild,
+    declared: &Declared,
+    graph: &graph::Graph,
+    index: &index::Entries,
+    active: &[bool],
+) -> Option<NativeIssue> {
+    let features = features(graph, index, active);
+    let listed = |name: &str| {
+        graph
+            .nodes
+            .iter()
+            .zip(active)
+            .find(|&(c, &a)| a && c.name.as_str() == name)
+            .map(|(c, _)| c)
+    };
+    let mut findings = vec![];
+    for (n, c) in graph.nodes.iter().enumerate().filter(|&(n, _)| active[n]) {
+        let links = index
+            .get(&c.name)
+            .and_then(|vs| vs.get(&c.ver))
+            .and_then(|entry| entry.links.clone());
+        let known = KNOWN.iter().find(|s| s.krate == c.name.as_str());
+        let bundling = match (known, &links) {
+            (Some(s), _) => s.features,
+            (None, Some(_)) => BUNDLING,
+            (None, None) => &[],
+        };
+        let problem = if let Some(f) = bundling.iter().find(|&&f| features[n].contains(f)) {
+            Some(Problem::Bundled(format!("feature {}", f)))
+        } else if let Some(src) = known.and_then(|s| s.source).and_then(listed) {
+            Some(Problem::Bundled(format!(
+                "{}-{} listed",
+                src.name.as_str(),
+                src.ver
+            )))
+        } else if let Some(var) = known
+            .and_then(|s| s.system_env)
+            .filter(|v| !declared.env.contains(v))
+        {
+            Some(Problem::Bundled(format!("{} not set", var)))
+        } else if known.map_or(false, |s| !declared.packages.contains(&s.package)) {
+            Some(Problem::MissingDependency)
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            findings.push(Finding {
+                id: c.clone(),
+                links,
+                package: known.map(|s| s.package.to_string()),
+                problem,
+            });
+        }
+    }
+    match findings.is_empty() {
+        true => None,
+        false => Some(NativeIssue {
+            ebuild: ebuild.clone(),
+            findings,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{add_package, dep, ebuild};
+
+    #[test]
+    fn declarations() {
+        let declared = declared(
+            "DEPEND=\"dev-libs/libgit2:=\n\t>=dev-libs/openssl-1.1.1:0=\"\n\
+             RDEPEND=\"${DEPEND}\n#\tnet-libs/libssh2\n\tdev-db/sqlite-compat\"\n\
+             src_configure() {\n\texport LIBGIT2_SYS_USE_PKG_CONFIG=1\n\
+             \t# export ZSTD_SYS_USE_PKG_CONFIG=1\n}\n",
+        );
+        assert_eq!(
+            vec!["dev-libs/openssl", "dev-libs/libgit2"],
+            declared.packages
+        );
+        assert_eq!(vec!["LIBGIT2_SYS_USE_PKG_CONFIG"], declared.env);
+    }
+
+    fn problems(content: &str, vendored: bool) -> Vec<String> {
+        let crates = vec![
+            dep("app", "1.0.0"),
+            dep("openssl-sys", "0.9.61"),
+            dep("libgit2-sys", "0.12.18+1.1.0"),
+            dep("libudev-sys", "0.1.4"),
+        ];
+        let mut index = index::Entries::new();
+        let features = if vendored { vec!["vendored"] } else { vec![] };
+        add_package(
+            &mut index,
+            &crates[0],
+            serde_json::json!({"deps": [
+                {"name": "openssl-sys", "req": "^0.9", "features": features},
+                {"name": "libgit2-sys", "req": "^0.12"},
+                {"name": "libudev-sys", "req": "^0.1"},
+            ]}),
+        );
+        for c in &crates[1..] {
+            add_package(&mut index, c, serde_json::json!({}));
+        }
+        let graph = graph::build(&crates, &index, Some("app"), &[]);
+        let issue = check(
+            &ebuild("gentoo", "app-misc/app/app-1.0.0.ebuild"),
+            &declared(content),
+            &graph,
+            &index,
+            &graph.reachable(true),
+        );
+        issue
+            .into_iter()
+            .flat_map(|i| i.findings)
+            .map(|f| format!("{} {:?}", f.id.name.as_str(), f.problem))
+            .collect()
+    }
+
+    #[test]
+    fn findings() {
+        assert_eq!(
+            vec![
+                "openssl-sys Bundled(\"feature vendored\")",
+                "libgit2-sys Bundled(\"LIBGIT2_SYS_USE_PKG_CONFIG not set\")",
+                "libudev-sys MissingDependency",
+            ],
+            problems("", true)
+        );
+        let content = "DEPEND=\"dev-libs/openssl:0= dev-libs/libgit2:= virtual/libudev\"\n\
+                       export LIBGIT2_SYS_USE_PKG_CONFIG=1\n";
+        assert!(problems(content, false).is_empty());
+        // Declaring the package doesn't make up for the bundled copy
+        assert_eq!(
+            vec!["openssl-sys Bundled(\"feature vendored\")"],
+            problems(content, true)
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Advisories in OSV format (https://ossf.github.io/osv-schema/), e.g. from GHSA, for crates.io
+//! vulnerabilities that have no RustSec entry (yet).
+
+use crate::{AdvisoryMeta, DepInfo};
+use anyhow::{Context, Result};
+use rustsec::package::Version;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+const ECOSYSTEM: &str = "crates.io";
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Record {
+    pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    summary: String,
+    published: Option<String>,
+    withdrawn: Option<String>,
+    #[serde(default)]
+    affected: Vec<Affected>,
+    #[serde(default)]
+    severity: Vec<Severity>,
+    #[serde(default)]
+    references: Vec<Reference>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Affected {
+    package: Package,
+    #[serde(default)]
+    ranges: Vec<Range>,
+    #[serde(default)]
+    versions: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Package {
+    ecosystem: String,
+    name: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Range {
+    #[serde(rename = "type")]
+    typ: String,
+    events: Vec<Event>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Event {
+    Introduced(String),
+    Fixed(String),
+    LastAffected(String),
+    Limit(String),
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Severity {
+    #[serde(rename = "type")]
+    typ: String,
+    score: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Reference {
+    #[serde(rename = "type")]
+    typ: String,
+    url: String,
+}
+
+/// Records from one directory or zip file
+pub struct Osv {
+    pub source: String,
+    records: Vec<Record>,
+    by_crate: HashMap<String, Vec<usize>>,
+}
+
+impl Osv {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = path.to_string_lossy().to_string();
+        let mut ret = Osv {
+            source,
+            records: vec![],
+            by_crate: HashMap::new(),
+        };
+        if path.is_dir() {
+            ret.load_dir(path)?;
+        } else {
+            ret.load_zip(path)?;
+        }
+        log::info!(
+            "{} OSV records for {} from {}",
+            ret.records.len(),
+            ECOSYSTEM,
+            ret.source
+        );
+        Ok(ret)
+    }
+
+    fn load_dir(&mut self, dir: &Path) -> Result<()> {
+        for entry in std::fs::read_dir(dir).context(format!("List {}", dir.display()))? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.load_dir(&path)?;
+            } else if path.extension().map_or(false, |e| e == "json") {
+                let content = std::fs::read(&path)?;
+                self.add(&path.to_string_lossy(), &content);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_zip(&mut self, path: &Path) -> Result<()> {
+        let file = std::fs::File::open(path).context(format!("Open {}", path.display()))?;
+        let mut archive = zip::ZipArchive::new(file).context("Read zip")?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if !file.name().ends_with(".json") {
+                continue;
+            }
+            let name = file.name().to_string();
+            let mut content = vec![];
+            file.read_to_end(&mut content)
+                .context(format!("Read {}", name))?;
+            self.add(&name, &content);
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, name: &str, content: &[u8]) {
+        let record = match serde_json::from_slice::<Record>(content) {
+            Ok(record) => record,
+            Err(e) => {
+                log::warn!("Skipping OSV record {}: {}", name, e);
+                return;
+            }
+        };
+        if record.withdrawn.is_some() {
+            return;
+        }
+        let index = self.records.len();
+        let mut crates = record
+            .affected
+            .iter()
+            .filter(|a| a.package.ecosystem == ECOSYSTEM)
+            .map(|a| a.package.name.clone())
+            .collect::<Vec<_>>();
+        crates.sort();
+        crates.dedup();
+        if crates.is_empty() {
+            return;
+        }
+        for name in crates {
+            self.by_crate
+                .entry(name)
+                .or_insert_with(Vec::new)
+                .push(index);
+        }
+        self.records.push(record);
+    }
+
+    pub fn query(&self, dep: &DepInfo) -> Vec<&Record> {
+        self.by_crate
+            .get(dep.name.as_str())
+            .into_iter()
+            .flatten()
+            .map(|&i| &self.records[i])
+            .filter(|r| r.affects(dep))
+            .collect()
+    }
+}
+
+impl Record {
+    fn affects(&self, dep: &DepInfo) -> bool {
+        self.affected
+            .iter()
+            .filter(|a| a.package.ecosystem == ECOSYSTEM && a.package.name == dep.name.as_str())
+            .any(|a| {
+                a.versions
+                    .iter()
+                    .any(|v| Version::from_str(v).map_or(false, |v| v == dep.ver))
+                    || a.ranges
+                        .iter()
+                        .filter(|r| r.typ == "SEMVER" || r.typ == "ECOSYSTEM")
+                        .any(|r| r.affects(&dep.ver))
+            })
+    }
+
+    /// Day of publication, YYYY-MM-DD
+    pub fn date(&self) -> Option<String> {
+        self.published
+            .as_ref()
+            .map(|p| p.chars().take(10).collect())
+    }
+
+    /// Patched version requirements as RustSec writes them: fixes in older lines only cover their
+    /// own line, e.g. ^0.6.14 and >=1.6.1
+    fn patched(&self) -> Vec<String> {
+        let mut fixed = self
+            .affected
+            .iter()
+            .filter(|a| a.package.ecosystem == ECOSYSTEM)
+            .flat_map(|a| &a.ranges)
+            .flat_map(|r| &r.events)
+            .filter_map(|e| match e {
+                Event::Fixed(v) => Version::from_str(v).ok(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        fixed.sort();
+        fixed.dedup();
+        let newest = fixed.len().saturating_sub(1);
+        fixed
+            .iter()
+            .enumerate()
+            .map(|(i, v)| match i == newest {
+                true => format!(">={}", v),
+                false => format!("^{}", v),
+            })
+            .collect()
+    }
+
+    pub fn meta(&self, source: &str) -> AdvisoryMeta {
+        AdvisoryMeta {
+            id: self.id.clone(),
+            title: match self.summary.is_empty() {
+                true => self.id.clone(),
+                false => self.summary.clone(),
+            },
+            cvss: self
+                .severity
+                .iter()
+                .filter(|s| s.typ == "CVSS_V3")
+                .find_map(|s| cvss::v3::base::Base::from_str(&s.score).ok()),
+            source: source.to_string(),
+            date: self.date(),
+            aliases: self.aliases.clone(),
+            patched: self.patched(),
+            url: self
+                .references
+                .iter()
+                .find(|r| r.typ == "ADVISORY")
+                .map(|r| r.url.clone()),
+            references: self.references.iter().map(|r| r.url.clone()).collect(),
+            withdrawn: self.withdrawn.is_some(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Range {
+    // Walk the events in version order, as the OSV spec describes
+    fn affects(&self, ver: &Version) -> bool {
+        let parse = |v: &str| match v {
+            "0" => Version::from_str("0.0.0").ok(),
+            v => Version::from_str(v).ok(),
+        };
+        let mut events = self
+            .events
+            .iter()
+            .filter_map(|e| {
+                let v = match e {
+                    Event::Introduced(v)
+                    | Event::Fixed(v)
+                    | Event::LastAffected(v)
+                    | Event::Limit(v) => v,
+                };
+                parse(v).map(|v| (v, e))
+            })
+            .collect::<Vec<_>>();
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut affected = false;
+        for (v, e) in events {
+            if &v > ver {
+                break;
+            }
+            match e {
+                Event::Introduced(_) => affected = true,
+                Event::Fixed(_) | Event::Limit(_) => affected = false,
+                Event::LastAffected(_) => affected = affected && &v == ver,
+            }
+        }
+        affected
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::dep;
+
+    #[test]
+    fn ranges() {
+        let mut osv = Osv {
+            source: "test".to_string(),
+            records: vec![],
+            by_crate: HashMap::new(),
+        };
+        osv.add("example", include_bytes!("tests/osv-example.json"));
+        assert_eq!(1, osv.query(&dep("smallvec", "0.6.13")).len());
+        assert_eq!(1, osv.query(&dep("smallvec", "1.6.0")).len());
+        assert_eq!(0, osv.query(&dep("smallvec", "1.6.1")).len());
+        assert_eq!(0, osv.query(&dep("smallvec", "0.6.14")).len());
+        assert_eq!(1, osv.query(&dep("smallvec", "0.3.0")).len());
+        assert_eq!(0, osv.query(&dep("tinyvec", "1.0.0")).len());
+        let meta = osv.records[0].meta("test");
+        assert_eq!(Some("2021-01-08".to_string()), osv.records[0].date());
+        assert!(meta.cvss.is_some());
+        assert_eq!(vec!["^0.6.14", ">=1.6.1"], meta.patched);
+    }
+}
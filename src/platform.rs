@@ -0,0 +1,75 @@
+//! Whether an advisory restricted to some OSes or architectures can matter for Gentoo, which
+//! builds for Linux on the arches an ebuild is keyworded for.
+
+use crate::AdvisoryMeta;
+
+/// Rust target architectures for an ebuild's KEYWORDS. Prefix keywords (e.g. x64-macos) and
+/// masks (-arch, -*) are left out.
+pub fn arches(keywords: &[String]) -> Vec<String> {
+    let mut ret = keywords
+        .iter()
+        .map(|k| k.trim_start_matches('~'))
+        .filter(|k| !k.is_empty() && !k.contains('-') && *k != "*")
+        .map(|k| rust_arch(k).to_string())
+        .collect::<Vec<_>>();
+    ret.sort();
+    ret.dedup();
+    ret
+}
+
+/// Some keywords stand for several targets: ppc64 for big and little endian powerpc64 (ppc64le
+/// has no keyword of its own), mips for all mips targets. Their endianness, and for mips the
+/// pointer width, stays unknown when evaluating target cfgs.
+fn rust_arch(gentoo: &str) -> &str {
+    match gentoo {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "ppc" => "powerpc",
+        "ppc64" => "powerpc64",
+        "riscv" => "riscv64",
+        "s390" => "s390x",
+        "sparc" => "sparc64",
+        "loong" => "loongarch64",
+        other => other,
+    }
+}
+
+/// arches: of all ebuilds using the affected crate, empty if unknown (e.g. live ebuilds)
+pub fn relevant(advisory: &AdvisoryMeta, arches: &[String]) -> bool {
+    if !advisory.os.is_empty() && !advisory.os.iter().any(|os| os == "linux") {
+        return false;
+    }
+    if !advisory.arch.is_empty()
+        && !arches.is_empty()
+        && !advisory.arch.iter().any(|a| arches.contains(a))
+    {
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keywords() {
+        let keywords = "amd64 ~arm64 -ppc x64-macos ~x86 -*"
+            .split_whitespace()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let arches = arches(&keywords);
+        assert_eq!(vec!["aarch64", "x86", "x86_64"], arches);
+
+        let advisory = |os: &[&str], arch: &[&str]| AdvisoryMeta {
+            os: os.iter().map(|s| s.to_string()).collect(),
+            arch: arch.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        assert!(relevant(&advisory(&[], &[]), &arches));
+        assert!(!relevant(&advisory(&["windows"], &[]), &arches));
+        assert!(relevant(&advisory(&["linux", "windows"], &[]), &arches));
+        assert!(!relevant(&advisory(&[], &["powerpc"]), &arches));
+        assert!(relevant(&advisory(&[], &["powerpc"]), &[]));
+    }
+}
@@ -0,0 +1,143 @@
+//! How crates are ranked in the output. A policy is an ordered list of criteria, each giving a
+//! number where higher means more urgent; crates are compared criterion by criterion. Read from a
+//! TOML file, e.g. for ranking by CVSS first:
+//!
+//! ```toml
+//! criteria = ["cvss", "status", "overlay:gentoo", "used"]
+//! ```
+
+use crate::CrateStatus;
+use anyhow::{Context, Result};
+use std::convert::TryFrom;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum Criterion {
+    /// 4 for vulnerabilities, 3 for yanked, 2 for informational notices, 1 if not in the index
+    Status,
+    Vulnerable,
+    Notice,
+    Yanked,
+    /// Not in the crates.io index
+    Unknown,
+    /// Highest CVSS score of the advisories, times 1000
+    Cvss,
+    /// Number of advisories
+    Advisories,
+    /// Number of ebuilds using the crate
+    Used,
+    /// Number of ebuilds in this overlay using the crate
+    Overlay(String),
+}
+
+impl TryFrom<String> for Criterion {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self> {
+        if let Some(overlay) = s.strip_prefix("overlay:") {
+            return Ok(Criterion::Overlay(overlay.to_string()));
+        }
+        Ok(match s.as_str() {
+            "status" => Criterion::Status,
+            "vulnerable" => Criterion::Vulnerable,
+            "notice" => Criterion::Notice,
+            "yanked" => Criterion::Yanked,
+            "unknown" => Criterion::Unknown,
+            "cvss" => Criterion::Cvss,
+            "advisories" => Criterion::Advisories,
+            "used" => Criterion::Used,
+            _ => anyhow::bail!("Unknown criterion {}", s),
+        })
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    criteria: Vec<Criterion>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            criteria: vec![
+                Criterion::Status,
+                Criterion::Overlay("gentoo".to_string()),
+                Criterion::Cvss,
+                Criterion::Used,
+            ],
+        }
+    }
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).context(format!("Read {}", path.to_string_lossy()))?;
+        let ret = toml::from_str::<Policy>(&content)
+            .context(format!("Parse {}", path.to_string_lossy()))?;
+        anyhow::ensure!(!ret.criteria.is_empty(), "Policy without criteria");
+        Ok(ret)
+    }
+
+    /// One value per criterion, compared in order
+    pub fn score(&self, status: &CrateStatus) -> Vec<i64> {
+        // Advisories for other platforms, suppressed or on crates no ebuild needs don't count
+        let unreachable = status.is_unreachable();
+        let advisories = status
+            .advisories
+            .iter()
+            .filter(|a| !unreachable && !a.irrelevant && !status.is_suppressed(&a.id))
+            .collect::<Vec<_>>();
+        let vulnerable = advisories.iter().any(|a| a.is_vulnerability());
+        let notice = !vulnerable && !advisories.is_empty();
+        self.criteria
+            .iter()
+            .map(|c| match c {
+                Criterion::Status => match (vulnerable, status.yanked, notice) {
+                    (true, _, _) => 4,
+                    (false, Some(true), _) => 3,
+                    (false, _, true) => 2,
+                    (false, None, false) => 1,
+                    (false, Some(false), false) => 0,
+                },
+                Criterion::Vulnerable => vulnerable as i64,
+                Criterion::Notice => notice as i64,
+                Criterion::Yanked => (status.yanked == Some(true)) as i64,
+                Criterion::Unknown => status.yanked.is_none() as i64,
+                Criterion::Cvss => advisories
+                    .iter()
+                    .filter_map(|a| a.cvss.as_ref().map(|v| (v.score().value() * 1000.0) as i64))
+                    .max()
+                    .unwrap_or(-1),
+                Criterion::Advisories => advisories.len() as i64,
+                Criterion::Used => status.ebuilds.len() as i64,
+                Criterion::Overlay(overlay) => status
+                    .ebuilds
+                    .iter()
+                    .filter(|e| &e.overlay == overlay)
+                    .count() as i64,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let policy =
+            toml::from_str::<Policy>(r#"criteria = ["cvss", "overlay:guru", "used"]"#).unwrap();
+        assert_eq!(
+            vec![
+                Criterion::Cvss,
+                Criterion::Overlay("guru".to_string()),
+                Criterion::Used
+            ],
+            policy.criteria
+        );
+        assert!(toml::from_str::<Policy>(r#"criteria = ["popularity"]"#).is_err());
+    }
+}
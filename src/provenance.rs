@@ -0,0 +1,62 @@
+//! What went into an analysis, so results can be reproduced and compared
+
+use crate::gitrepo;
+use chrono::TimeZone;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Input {
+    pub url: Option<String>,
+    pub commit: String,
+    pub time: String,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Provenance {
+    pub tool: String,
+    pub version: String,
+    pub started: String,
+    pub finished: String,
+    // meta, crates.io and rustsec
+    pub repositories: BTreeMap<String, Input>,
+    pub overlays: BTreeMap<String, Input>,
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    repositories: dashmap::DashMap<String, Input>,
+    overlays: dashmap::DashMap<String, Input>,
+}
+
+impl Input {
+    fn of(repo: &gitrepo::RepoRepo, commit: &git2::Commit) -> Self {
+        Input {
+            url: repo.url(),
+            commit: commit.id().to_string(),
+            time: chrono::Utc
+                .timestamp(commit.time().seconds(), 0)
+                .to_rfc3339(),
+        }
+    }
+}
+
+impl Recorder {
+    pub fn repository(&self, name: &str, repo: &gitrepo::RepoRepo, commit: &git2::Commit) {
+        self.repositories
+            .insert(name.to_string(), Input::of(repo, commit));
+    }
+    pub fn overlay(&self, name: &str, repo: &gitrepo::RepoRepo, commit: &git2::Commit) {
+        self.overlays
+            .insert(name.to_string(), Input::of(repo, commit));
+    }
+    pub fn finish(self, started: chrono::DateTime<chrono::Utc>) -> Provenance {
+        Provenance {
+            tool: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started: started.to_rfc3339(),
+            finished: chrono::Utc::now().to_rfc3339(),
+            repositories: self.repositories.into_iter().collect(),
+            overlays: self.overlays.into_iter().collect(),
+        }
+    }
+}
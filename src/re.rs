@@ -3,6 +3,10 @@ lazy_static::lazy_static! {
     pub static ref CRATES: Regex = RegexBuilder::new("\\n *CRATES=\"(.*?)\" *(#.*)?\n").dot_matches_new_line(true).build().unwrap();
     pub static ref DEPSPEC: Regex = Regex::new(r"^([a-zA-Z0-9_\-]+)-([0-9]+\.[0-9]+\.[0-9]+.*)$").unwrap();
     pub static ref USES_CARGO_ECLASS: Regex = Regex::new(r"\n[ \t]*inherit.*?cargo").unwrap();
+    pub static ref KEYWORDS: Regex = Regex::new(r#"\n[ \t]*KEYWORDS="([^"]*)""#).unwrap();
+    pub static ref RUST_MIN: Regex = Regex::new(r#"(?:RUST_MIN_VER="?|>=(?:virtual/rust|dev-lang/rust(?:-bin)?)-)([0-9]+(?:\.[0-9]+){1,2})"#).unwrap();
+    pub static ref DEPEND_VARS: Regex = Regex::new(r#"(?:^|\n)[ \t]*[BIPR]?DEPEND\+?="([^"]*)""#).unwrap();
+    pub static ref NON_WHITESPACE: Regex = Regex::new(r"\S+").unwrap();
 
     // Based on site-packages/portage/versions.py... meh, complicated
     pub static ref EBUILD_DOTS:  Regex = Regex::new(r"/(?P<pn>[\w+][\w+.-]*?(?P<pn_inval>-(-r(\d+))?)?)-(?P<ver>(\d+)((\.\d+)*)([a-z]?)((_(pre|p|beta|alpha|rc)\d*)*))(-r(?P<rev>\d+))?\.ebuild$").unwrap();
@@ -0,0 +1,350 @@
+use crate::{native, CrateStatus, Ebuild, Output, OPTS};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Clap, Debug)]
+pub struct ReportOpts {
+    /// Analysis result to read, defaults to status.json in the work dir
+    #[clap(long, short = 'i')]
+    input: Option<PathBuf>,
+    /// Only show ebuilds from these overlays
+    #[clap(long = "overlay")]
+    overlays: Vec<String>,
+    /// Only show these crates
+    #[clap(long = "crate")]
+    crates: Vec<String>,
+    /// Also show crates that have no advisories and aren't yanked
+    #[clap(long)]
+    all: bool,
+    /// Write the filtered result as JSON instead of text
+    #[clap(long)]
+    json: bool,
+    /// Also show crates used in several versions, over all overlays
+    #[clap(long)]
+    fragmentation: bool,
+}
+
+impl ReportOpts {
+    fn filter(&self, mut status: CrateStatus) -> Option<CrateStatus> {
+        if !self.crates.is_empty() && !self.crates.iter().any(|c| c == status.id.name.as_str()) {
+            return None;
+        }
+        if !self.all && status.advisories.is_empty() && status.yanked != Some(true) {
+            return None;
+        }
+        if !self.overlays.is_empty() {
+            status
+                .ebuilds
+                .retain(|e| self.overlays.contains(&e.overlay));
+            if status.ebuilds.is_empty() {
+                return None;
+            }
+        }
+        Some(status)
+    }
+}
+
+pub(crate) fn read_output(path: &Path) -> Result<Output> {
+    let file = std::fs::File::open(path).context(format!("Open {}", path.to_string_lossy()))?;
+    serde_json::from_reader(std::io::BufReader::new(file))
+        .context(format!("Parse {}", path.to_string_lossy()))
+}
+
+/// overlay::path, or overlay::category/package-version if only the newest ebuilds were audited
+fn ebuild_name(e: &Ebuild, per_package: bool) -> String {
+    let stem = e.path.strip_suffix(".ebuild").unwrap_or(&e.path);
+    match (per_package, e.path.find('/'), stem.rfind('/')) {
+        (true, Some(cat), Some(file)) => {
+            format!("{}::{}/{}", e.overlay, &e.path[..cat], &stem[file + 1..])
+        }
+        _ => format!("{}::{}", e.overlay, e.path),
+    }
+}
+
+pub fn report(opts: &ReportOpts) -> Result<()> {
+    let input = opts.input.clone().unwrap_or_else(|| OPTS.status_path());
+    let mut output = read_output(&input)?;
+    output.status = output
+        .status
+        .into_iter()
+        .filter_map(|s| opts.filter(s))
+        .collect();
+    if !opts.fragmentation {
+        output.fragmentation.clear();
+    }
+    if !opts.crates.is_empty() {
+        output
+            .fragmentation
+            .retain(|f| opts.crates.iter().any(|c| c == f.name.as_str()));
+    }
+    if !opts.overlays.is_empty() {
+        output
+            .toolchains
+            .retain(|t| opts.overlays.contains(&t.ebuild.overlay));
+        output
+            .msrv
+            .retain(|m| opts.overlays.contains(&m.ebuild.overlay));
+        output
+            .incomplete
+            .retain(|i| opts.overlays.contains(&i.ebuild.overlay));
+        output
+            .unreachable
+            .retain(|u| opts.overlays.contains(&u.ebuild.overlay));
+        output
+            .unchecked
+            .retain(|e| opts.overlays.contains(&e.overlay));
+        output
+            .native
+            .retain(|n| opts.overlays.contains(&n.ebuild.overlay));
+        output
+            .superseded
+            .retain(|s| opts.overlays.contains(&s.ebuild.overlay));
+        output
+            .affected
+            .retain(|a| opts.overlays.contains(&a.overlay));
+    }
+
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    if opts.json {
+        serde_json::to_writer_pretty(&mut out, &output).context("Write report")?;
+        writeln!(out)?;
+        return Ok(());
+    }
+    for status in &output.status {
+        writeln!(out, "{} {}", status.id.name.as_str(), status.id.ver)?;
+        for a in &status.advisories {
+            let cvss = a
+                .cvss
+                .as_ref()
+                .map(|cvss| format!(" (CVSS {})", cvss.score().value()))
+                .unwrap_or_default();
+            let kind = a
+                .informational
+                .as_ref()
+                .map(|i| format!(" ({})", i))
+                .unwrap_or_default();
+            let suppressed = match (a.irrelevant, status.is_suppressed(&a.id)) {
+                (_, true) => " [suppressed]",
+                (true, false) => " [other platforms]",
+                (false, false) => "",
+            };
+            writeln!(out, "  {}{}{}{}: {}", a.id, kind, cvss, suppressed, a.title)?;
+            if !a.aliases.is_empty() {
+                writeln!(out, "    aka {}", a.aliases.join(" "))?;
+            }
+        }
+        for s in &status.suppressed {
+            writeln!(
+                out,
+                "  {} suppressed for {} ebuild(s) {} {}: {}",
+                s.advisory,
+                s.ebuilds.len(),
+                if s.expired { "EXPIRED on" } else { "until" },
+                s.expires,
+                s.reason
+            )?;
+        }
+        if let Some(upgrade) = &status.upgrade {
+            let show = |v: &Option<rustsec::package::Version>| match v {
+                Some(v) => v.to_string(),
+                None => "none".to_string(),
+            };
+            writeln!(
+                out,
+                "  fixed in: {} (semver-compatible), {} (overall)",
+                show(&upgrade.compatible),
+                show(&upgrade.overall)
+            )?;
+        }
+        match status.yanked {
+            Some(true) => writeln!(out, "  yanked")?,
+            None => writeln!(out, "  not in crates.io index")?,
+            Some(false) => (),
+        }
+        for e in &status.ebuilds {
+            let usage = match (status.unreachable.contains(e), status.foreign.contains(e)) {
+                (true, _) => " (not needed)",
+                (false, true) => " (other platforms only)",
+                (false, false) => "",
+            };
+            writeln!(out, "    {}{}", ebuild_name(e, output.latest), usage)?;
+            for p in status.paths.iter().filter(|p| &p.ebuild == e) {
+                if p.path.len() > 1 {
+                    let path = p
+                        .path
+                        .iter()
+                        .map(|d| format!("{} {}", d.name.as_str(), d.ver))
+                        .collect::<Vec<_>>();
+                    writeln!(out, "      via {}", path.join(" > "))?;
+                }
+            }
+        }
+    }
+    if !output.msrv.is_empty() {
+        writeln!(out, "Rust version too old:")?;
+    }
+    for m in &output.msrv {
+        match m.undeclared {
+            true => writeln!(
+                out,
+                "  {} declares no minimum Rust, the oldest is {}, needs {}",
+                ebuild_name(&m.ebuild, output.latest),
+                m.declared,
+                m.required
+            )?,
+            false => writeln!(
+                out,
+                "  {} allows Rust {}, needs {}",
+                ebuild_name(&m.ebuild, output.latest),
+                m.declared,
+                m.required
+            )?,
+        }
+        for c in &m.crates {
+            writeln!(out, "    {} {}", c.name.as_str(), c.ver)?;
+        }
+    }
+    if !output.incomplete.is_empty() {
+        writeln!(out, "Incomplete CRATES:")?;
+    }
+    for i in &output.incomplete {
+        writeln!(out, "  {}", ebuild_name(&i.ebuild, output.latest))?;
+        for u in &i.unsatisfied {
+            writeln!(
+                out,
+                "    {} {} needs {} {}",
+                u.from.name.as_str(),
+                u.from.ver,
+                u.name,
+                u.req
+            )?;
+        }
+    }
+    if !output.unreachable.is_empty() {
+        writeln!(out, "Unneeded CRATES:")?;
+    }
+    for u in &output.unreachable {
+        let crates = u
+            .crates
+            .iter()
+            .map(|c| format!("{}-{}", c.name.as_str(), c.ver))
+            .collect::<Vec<_>>();
+        writeln!(
+            out,
+            "  {}: {}",
+            ebuild_name(&u.ebuild, output.latest),
+            crates.join(" ")
+        )?;
+    }
+    if !output.unchecked.is_empty() {
+        writeln!(
+            out,
+            "Not checked for unneeded CRATES, own crate not listed: {} ebuilds",
+            output.unchecked.len()
+        )?;
+    }
+    if !output.native.is_empty() {
+        writeln!(out, "Native libraries:")?;
+    }
+    for n in &output.native {
+        writeln!(out, "  {}", ebuild_name(&n.ebuild, output.latest))?;
+        for f in &n.findings {
+            let library = f
+                .package
+                .as_deref()
+                .or_else(|| f.links.as_deref())
+                .unwrap_or("?");
+            match &f.problem {
+                native::Problem::Bundled(why) => writeln!(
+                    out,
+                    "    {} {}: bundled {} ({})",
+                    f.id.name.as_str(),
+                    f.id.ver,
+                    library,
+                    why
+                )?,
+                native::Problem::MissingDependency => writeln!(
+                    out,
+                    "    {} {}: no dependency on {}",
+                    f.id.name.as_str(),
+                    f.id.ver,
+                    library
+                )?,
+            }
+        }
+    }
+    if !output.superseded.is_empty() {
+        writeln!(
+            out,
+            "Vulnerable, but a newer version is fixed (remove or mask):"
+        )?;
+    }
+    for s in &output.superseded {
+        writeln!(
+            out,
+            "  {}: {} (fixed in {})",
+            ebuild_name(&s.ebuild, output.latest),
+            s.advisories.join(", "),
+            s.fixed.path
+        )?;
+    }
+    if !output.affected.is_empty() {
+        writeln!(out, "Vulnerable in every version:")?;
+    }
+    for a in &output.affected {
+        writeln!(
+            out,
+            "  {}::{}: {} ({} ebuilds)",
+            a.overlay,
+            a.package,
+            a.advisories.join(", "),
+            a.ebuilds.len()
+        )?;
+    }
+    if !output.toolchains.is_empty() {
+        writeln!(out, "Rust toolchains:")?;
+    }
+    for t in &output.toolchains {
+        writeln!(out, "  {}::{}", t.ebuild.overlay, t.ebuild.path)?;
+        for a in &t.advisories {
+            writeln!(out, "    {}: {}", a.id, a.title)?;
+        }
+    }
+    if !output.fragmentation.is_empty() {
+        let versions = output
+            .fragmentation
+            .iter()
+            .map(|f| f.versions)
+            .sum::<usize>();
+        let savings = output
+            .fragmentation
+            .iter()
+            .map(|f| f.savings)
+            .sum::<usize>();
+        writeln!(
+            out,
+            "Fragmentation: {} versions of {} crates used in more than one version, \
+             {} distfiles fewer on converging",
+            versions,
+            output.fragmentation.len(),
+            savings
+        )?;
+    }
+    for f in &output.fragmentation {
+        writeln!(
+            out,
+            "  {}: {} versions in {} ebuilds, {} fewer",
+            f.name.as_str(),
+            f.versions,
+            f.ebuilds,
+            f.savings
+        )?;
+        for l in &f.lines {
+            let versions = l.versions.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+            writeln!(out, "    ^{}: {}", l.line, versions.join(" "))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Advisories known not to matter for (some) ebuilds, e.g. because the affected code is
+//! Windows-only or behind a feature the ebuild doesn't enable. Read from a TOML file:
+//!
+//! ```toml
+//! [[suppress]]
+//! advisory = "RUSTSEC-2020-0071"
+//! crate = "time"                 # optional, as are version, overlay and atom
+//! version = "0.1.43"
+//! overlay = "gentoo"
+//! atom = "dev-util/foo"          # or =dev-util/foo-1.2 for a single ebuild
+//! reason = "Only affects the Windows backend"
+//! expires = "2022-06-30"
+//! ```
+//!
+//! Suppressed findings stay in the output, marked. Expired suppressions no longer suppress, but
+//! are still reported so they get reviewed.
+
+use crate::{CrateStatus, Ebuild};
+use anyhow::{Context, Result};
+use rustsec::package::Version;
+use std::path::Path;
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    advisory: String,
+    #[serde(rename = "crate")]
+    krate: Option<String>,
+    version: Option<Version>,
+    overlay: Option<String>,
+    atom: Option<String>,
+    reason: String,
+    expires: chrono::NaiveDate,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuppressionFile {
+    #[serde(default)]
+    suppress: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Suppressed {
+    pub advisory: String,
+    pub ebuilds: Vec<Ebuild>,
+    pub reason: String,
+    pub expires: String,
+    pub expired: bool,
+}
+
+pub struct Suppressions {
+    rules: Vec<Rule>,
+    today: chrono::NaiveDate,
+}
+
+impl Suppressions {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content =
+            std::fs::read_to_string(path).context(format!("Read {}", path.to_string_lossy()))?;
+        let ret = Self::parse(&content, chrono::Utc::today().naive_utc())
+            .context(format!("Parse {}", path.to_string_lossy()))?;
+        for rule in ret.rules.iter().filter(|r| r.expires < ret.today) {
+            log::warn!(
+                "Suppression of {} expired on {}: {}",
+                rule.advisory,
+                rule.expires,
+                rule.reason
+            );
+        }
+        Ok(ret)
+    }
+
+    fn parse(content: &str, today: chrono::NaiveDate) -> Result<Self> {
+        let file = toml::from_str::<SuppressionFile>(content)?;
+        for rule in &file.suppress {
+            anyhow::ensure!(
+                !rule.reason.trim().is_empty(),
+                "Suppression of {} has no reason",
+                rule.advisory
+            );
+        }
+        Ok(Suppressions {
+            rules: file.suppress,
+            today,
+        })
+    }
+
+    /// Record which of the status' findings are suppressed, and by which rule
+    pub fn apply(&self, status: &mut CrateStatus) {
+        for rule in &self.rules {
+            if !status.advisories.iter().any(|a| a.id == rule.advisory) {
+                continue;
+            }
+            if rule
+                .krate
+                .as_deref()
+                .map_or(false, |c| c != status.id.name.as_str())
+                || rule.version.as_ref().map_or(false, |v| v != &status.id.ver)
+            {
+                continue;
+            }
+            let ebuilds = status
+                .ebuilds
+                .iter()
+                .filter(|e| rule.matches(e))
+                .cloned()
+                .collect::<Vec<_>>();
+            if ebuilds.is_empty() {
+                continue;
+            }
+            status.suppressed.push(Suppressed {
+                advisory: rule.advisory.clone(),
+                ebuilds,
+                reason: rule.reason.clone(),
+                expires: rule.expires.to_string(),
+                expired: rule.expires < self.today,
+            });
+        }
+    }
+}
+
+impl Rule {
+    fn matches(&self, ebuild: &Ebuild) -> bool {
+        if self
+            .overlay
+            .as_ref()
+            .map_or(false, |o| o != &ebuild.overlay)
+        {
+            return false;
+        }
+        match &self.atom {
+            None => true,
+            Some(atom) => match atom.strip_prefix('=') {
+                Some(cpv) => ebuild_cpv(ebuild) == cpv,
+                None => ebuild.package() == atom,
+            },
+        }
+    }
+}
+
+/// category/package-version
+fn ebuild_cpv(ebuild: &Ebuild) -> String {
+    let category = ebuild.path.split('/').next().unwrap_or_default();
+    let file = match ebuild.path.rfind('/') {
+        Some(i) => &ebuild.path[i + 1..],
+        None => &ebuild.path,
+    };
+    format!("{}/{}", category, file.trim_end_matches(".ebuild"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{dep, ebuild};
+    use crate::AdvisoryMeta;
+
+    #[test]
+    fn suppress() {
+        let suppressions = Suppressions::parse(
+            r#"
+            [[suppress]]
+            advisory = "RUSTSEC-2020-0071"
+            atom = "=a/b-1"
+            reason = "Not built"
+            expires = "2021-06-30"
+
+            [[suppress]]
+            advisory = "RUSTSEC-2020-0071"
+            crate = "time"
+            overlay = "guru"
+            reason = "Windows only"
+            expires = "2021-01-01"
+            "#,
+            chrono::NaiveDate::from_ymd(2021, 3, 1),
+        )
+        .unwrap();
+        let mut status = CrateStatus {
+            advisories: vec![AdvisoryMeta {
+                id: "RUSTSEC-2020-0071".to_string(),
+                title: String::new(),
+                cvss: None,
+                source: crate::RUSTSEC_REPO.to_string(),
+                ..Default::default()
+            }],
+            ebuilds: vec![
+                ebuild("gentoo", "a/b/b-1.ebuild"),
+                ebuild("gentoo", "a/b/b-10.ebuild"),
+                ebuild("guru", "c/d/d-1.ebuild"),
+            ],
+            ..crate::testing::status(dep("time", "0.1.43"))
+        };
+        suppressions.apply(&mut status);
+        assert_eq!(2, status.suppressed.len());
+        assert_eq!(
+            vec![ebuild("gentoo", "a/b/b-1.ebuild")],
+            status.suppressed[0].ebuilds
+        );
+        assert!(!status.suppressed[0].expired);
+        assert_eq!(
+            vec![ebuild("guru", "c/d/d-1.ebuild")],
+            status.suppressed[1].ebuilds
+        );
+        assert!(status.suppressed[1].expired);
+        assert!(!status.is_suppressed("RUSTSEC-2020-0071"));
+
+        assert!(Suppressions::parse(
+            "[[suppress]]\nadvisory = \"X\"\nreason = \" \"\nexpires = \"2021-01-01\"",
+            chrono::NaiveDate::from_ymd(2021, 3, 1),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,209 @@
+//! Evaluating the target of platform-specific dependencies, either a cfg expression like
+//! cfg(all(unix, not(target_os = "macos"))) or a plain target triple, for Linux on some arch.
+//! Anything not known about the target, e.g. features, evaluates as unknown, and unknown counts as
+//! active so nothing is dismissed by mistake.
+
+#[derive(Debug, PartialEq, Eq)]
+enum Cfg {
+    Name(String),
+    KeyValue(String, String),
+    All(Vec<Cfg>),
+    Any(Vec<Cfg>),
+    Not(Box<Cfg>),
+}
+
+/// Whether a dependency on the target is used on Linux for any of the arches (Rust names).
+/// Without arches, any Linux will do.
+pub fn active_on_linux(target: &str, arches: &[String]) -> bool {
+    let target = target.trim();
+    if let Some(expr) = target
+        .strip_prefix("cfg(")
+        .and_then(|t| t.strip_suffix(')'))
+    {
+        let cfg = match parse(expr) {
+            Some(cfg) => cfg,
+            None => {
+                log::debug!("Can't parse target {}", target);
+                return true;
+            }
+        };
+        return match arches.is_empty() {
+            true => eval(&cfg, None) != Some(false),
+            false => arches.iter().any(|a| eval(&cfg, Some(a)) != Some(false)),
+        };
+    }
+    // A triple, e.g. x86_64-unknown-linux-gnu
+    let arch = target.split('-').next().and_then(triple_arch);
+    target.contains("-linux")
+        && (arches.is_empty() || arch.map_or(true, |arch| arches.iter().any(|a| a == arch)))
+}
+
+/// The Rust arch of the first part of a triple, e.g. x86 for i686
+fn triple_arch(arch: &str) -> Option<&str> {
+    match arch {
+        "i386" | "i586" | "i686" => Some("x86"),
+        "powerpc64le" => Some("powerpc64"),
+        "riscv64gc" => Some("riscv64"),
+        "x86_64" | "aarch64" | "powerpc" | "powerpc64" | "s390x" | "sparc64" | "loongarch64" => {
+            Some(arch)
+        }
+        _ if arch.starts_with("arm") || arch.starts_with("thumb") => Some("arm"),
+        // The mips keyword covers all of them
+        _ if arch.starts_with("mips") => Some("mips"),
+        _ => None,
+    }
+}
+
+fn eval(cfg: &Cfg, arch: Option<&str>) -> Option<bool> {
+    match cfg {
+        Cfg::Name(name) => match name.as_str() {
+            "unix" => Some(true),
+            "windows" | "test" | "debug_assertions" | "miri" => Some(false),
+            _ => None,
+        },
+        Cfg::KeyValue(key, value) => match key.as_str() {
+            "target_os" => Some(value == "linux"),
+            "target_family" => Some(value == "unix"),
+            "target_vendor" => Some(value == "unknown"),
+            "target_env" => match value.as_str() {
+                "gnu" | "musl" => None,
+                _ => Some(false),
+            },
+            "target_arch" => match arch {
+                // The mips keyword covers mips64 as well
+                Some("mips") if value.starts_with("mips") => None,
+                _ => arch.map(|a| a == value),
+            },
+            "target_pointer_width" => arch.and_then(pointer_width).map(|w| w == value),
+            "target_endian" => arch.and_then(endian).map(|e| e == value),
+            _ => None,
+        },
+        Cfg::All(cfgs) => cfgs
+            .iter()
+            .fold(Some(true), |acc, c| match (acc, eval(c, arch)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }),
+        Cfg::Any(cfgs) => cfgs
+            .iter()
+            .fold(Some(false), |acc, c| match (acc, eval(c, arch)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }),
+        Cfg::Not(cfg) => eval(cfg, arch).map(|b| !b),
+    }
+}
+
+fn pointer_width(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" | "aarch64" | "powerpc64" | "riscv64" | "s390x" | "sparc64" | "loongarch64" => {
+            Some("64")
+        }
+        "x86" | "arm" | "powerpc" => Some("32"),
+        // mips keywords cover n32 and n64 as well
+        _ => None,
+    }
+}
+
+fn endian(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" | "aarch64" | "x86" | "arm" | "riscv64" | "loongarch64" => Some("little"),
+        "powerpc" | "s390x" | "sparc64" => Some("big"),
+        // ppc64 and mips keywords cover both
+        _ => None,
+    }
+}
+
+fn parse(expr: &str) -> Option<Cfg> {
+    let (cfg, rest) = parse_cfg(expr.trim_start())?;
+    match rest.trim().is_empty() {
+        true => Some(cfg),
+        false => None,
+    }
+}
+
+fn parse_cfg(s: &str) -> Option<(Cfg, &str)> {
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    let (ident, rest) = s.split_at(end);
+    if ident.is_empty() {
+        return None;
+    }
+    let rest = rest.trim_start();
+    if let Some(rest) = rest.strip_prefix('=') {
+        let rest = rest.trim_start().strip_prefix('"')?;
+        let close = rest.find('"')?;
+        let cfg = Cfg::KeyValue(ident.to_string(), rest[..close].to_string());
+        return Some((cfg, rest[close + 1..].trim_start()));
+    }
+    if let Some(mut rest) = rest.strip_prefix('(') {
+        let mut cfgs = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(r) = rest.strip_prefix(')') {
+                rest = r.trim_start();
+                break;
+            }
+            let (cfg, r) = parse_cfg(rest)?;
+            cfgs.push(cfg);
+            rest = r.strip_prefix(',').unwrap_or(r);
+        }
+        let cfg = match ident {
+            "all" => Cfg::All(cfgs),
+            "any" => Cfg::Any(cfgs),
+            "not" if cfgs.len() == 1 => Cfg::Not(Box::new(cfgs.pop()?)),
+            _ => return None,
+        };
+        return Some((cfg, rest));
+    }
+    Some((Cfg::Name(ident.to_string()), rest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn targets() {
+        let arches = vec!["x86_64".to_string(), "aarch64".to_string()];
+        assert!(!active_on_linux("cfg(windows)", &arches));
+        assert!(active_on_linux("cfg(unix)", &arches));
+        assert!(!active_on_linux(
+            r#"cfg(any(target_os = "macos", target_os = "ios"))"#,
+            &arches
+        ));
+        assert!(active_on_linux(
+            r#"cfg(all(unix, not(target_os = "macos")))"#,
+            &arches
+        ));
+        assert!(!active_on_linux(r#"cfg(target_arch = "wasm32")"#, &arches));
+        assert!(active_on_linux(r#"cfg(target_arch = "wasm32")"#, &[]));
+        assert!(active_on_linux(r#"cfg(feature = "foo")"#, &arches));
+        assert!(!active_on_linux("x86_64-pc-windows-gnu", &arches));
+        assert!(active_on_linux("aarch64-unknown-linux-gnu", &arches));
+        assert!(!active_on_linux(
+            r#"cfg(all(target_os = "linux", target_pointer_width = "32"))"#,
+            &arches
+        ));
+        let x86 = vec!["x86".to_string()];
+        assert!(active_on_linux("i686-unknown-linux-gnu", &x86));
+        assert!(active_on_linux("i586-unknown-linux-musl", &x86));
+        assert!(!active_on_linux("x86_64-unknown-linux-gnu", &x86));
+        assert!(active_on_linux(
+            "armv7-unknown-linux-gnueabihf",
+            &["arm".to_string()]
+        ));
+        assert!(active_on_linux("csky-unknown-linux-gnuabiv2", &x86));
+        let mips = vec!["mips".to_string()];
+        assert!(active_on_linux("mips64el-unknown-linux-gnuabi64", &mips));
+        assert!(active_on_linux(r#"cfg(target_arch = "mips64")"#, &mips));
+        assert!(!active_on_linux(r#"cfg(target_arch = "x86")"#, &mips));
+        assert!(active_on_linux(
+            r#"cfg(target_pointer_width = "64")"#,
+            &mips
+        ));
+    }
+}
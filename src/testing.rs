@@ -0,0 +1,63 @@
+//! Values the tests of several modules build, with everything not under test left empty.
+
+use crate::{index, CrateStatus, DepInfo, Ebuild, EbuildDeps, EbuildInfo, RegistryPackage};
+use std::str::FromStr;
+
+pub fn dep(name: &str, ver: &str) -> DepInfo {
+    DepInfo {
+        name: FromStr::from_str(name).unwrap(),
+        ver: FromStr::from_str(ver).unwrap(),
+    }
+}
+
+pub fn ebuild(overlay: &str, path: &str) -> Ebuild {
+    Ebuild {
+        overlay: overlay.to_string(),
+        path: path.to_string(),
+    }
+}
+
+/// An ebuild in the gentoo overlay using the crates, with space-separated keywords
+pub fn add_ebuild(deps: &EbuildDeps, path: &str, crates: &[DepInfo], keywords: &str) {
+    deps.insert(
+        ebuild("gentoo", path),
+        EbuildInfo {
+            deps: crates.to_vec(),
+            keywords: keywords.split_whitespace().map(String::from).collect(),
+            rust_min: None,
+            native: crate::native::declared(""),
+        },
+    );
+}
+
+/// An index entry for the crate, fields being any beyond name, version and yanked, e.g. deps
+pub fn add_package(index: &mut index::Entries, c: &DepInfo, fields: serde_json::Value) {
+    let mut package = serde_json::json!({
+        "name": c.name.as_str(),
+        "vers": c.ver.to_string(),
+        "yanked": false,
+    });
+    if let (Some(package), Some(fields)) = (package.as_object_mut(), fields.as_object()) {
+        package.extend(fields.clone());
+    }
+    index.entry(c.name.clone()).or_default().insert(
+        c.ver.clone(),
+        serde_json::from_value::<RegistryPackage>(package).unwrap(),
+    );
+}
+
+/// A crate without advisories or findings, not yanked
+pub fn status(id: DepInfo) -> CrateStatus {
+    CrateStatus {
+        id,
+        advisories: vec![],
+        yanked: Some(false),
+        ebuilds: vec![],
+        suppressed: vec![],
+        upgrade: None,
+        score: vec![],
+        paths: vec![],
+        unreachable: vec![],
+        foreign: vec![],
+    }
+}
@@ -0,0 +1,428 @@
+//! When did vulnerable crates enter and leave the CRATES lists of each package? Found by walking
+//! the first-parent history of the overlay repositories and re-parsing packages whose ebuilds
+//! changed.
+
+use crate::{advisories, gitrepo, parse_crates, uses_cargo, DepInfo, OPTS};
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+const DAY: f64 = 86400.0;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Event {
+    commit: String,
+    time: i64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Interval {
+    introduced: Event,
+    removed: Option<Event>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Published {
+    id: String,
+    date: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Exposure {
+    overlay: String,
+    package: String,
+    #[serde(rename = "crate")]
+    id: DepInfo,
+    advisories: Vec<Published>,
+    maintainers: Vec<String>,
+    intervals: Vec<Interval>,
+    // Days the crate was listed after the first advisory's publication, summed over all
+    // intervals, if it is gone by now
+    days_to_fix: Option<f64>,
+    // Same, counting the last interval up to the analysed commit, if it is still listed
+    days_open: Option<f64>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Stats {
+    exposures: usize,
+    resolved: usize,
+    open: usize,
+    mean_days_to_fix: Option<f64>,
+    median_days_to_fix: Option<f64>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Timeline {
+    exposures: Vec<Exposure>,
+    overlays: BTreeMap<String, Stats>,
+    maintainers: BTreeMap<String, Stats>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct PkgMetadata {
+    #[serde(default)]
+    maintainer: Vec<Maintainer>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Maintainer {
+    email: String,
+}
+
+pub fn trace(overlays: &[String], sec_db: &advisories::Advisories) -> Timeline {
+    let published = dashmap::DashMap::new();
+    let advisories_of = |dep: &DepInfo| -> Vec<Published> {
+        published
+            .entry(dep.clone())
+            .or_insert_with(|| {
+                sec_db
+                    .query(dep)
+                    .into_iter()
+                    .filter(|f| f.is_vulnerability())
+                    .filter_map(|f| {
+                        Some(Published {
+                            id: f.id().to_string(),
+                            date: f.date()?,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .clone()
+    };
+
+    let mut exposures = overlays
+        .par_iter()
+        .flat_map(|overlay| match trace_overlay(overlay, &advisories_of) {
+            Ok(exposures) => exposures,
+            Err(e) => {
+                log::error!(
+                    "Failed to trace history of overlay {}:{}",
+                    overlay,
+                    crate::format_chain(&e),
+                );
+                vec![]
+            }
+        })
+        .collect::<Vec<_>>();
+    exposures.sort_by(|a, b| {
+        (&a.overlay, &a.package, a.id.name.as_str(), &a.id.ver).cmp(&(
+            &b.overlay,
+            &b.package,
+            b.id.name.as_str(),
+            &b.id.ver,
+        ))
+    });
+
+    let mut overlays = BTreeMap::new();
+    let mut maintainers = BTreeMap::new();
+    for e in &exposures {
+        overlays
+            .entry(e.overlay.clone())
+            .or_insert_with(Vec::new)
+            .push(e);
+        for m in &e.maintainers {
+            maintainers
+                .entry(m.clone())
+                .or_insert_with(Vec::new)
+                .push(e);
+        }
+    }
+    let overlays = overlays.into_iter().map(|(k, v)| (k, stats(&v))).collect();
+    let maintainers = maintainers
+        .into_iter()
+        .map(|(k, v)| (k, stats(&v)))
+        .collect();
+
+    Timeline {
+        exposures,
+        overlays,
+        maintainers,
+    }
+}
+
+fn trace_overlay(
+    overlay: &str,
+    advisories_of: &(impl Fn(&DepInfo) -> Vec<Published> + Sync),
+) -> Result<Vec<Exposure>> {
+    let repopath = OPTS.repo_path(overlay);
+    if !repopath.exists() {
+        return Ok(vec![]);
+    }
+    let repo = gitrepo::RepoRepo::on(&repopath)?;
+    let git = repo.repo();
+    if git.head().is_err() {
+        return Ok(vec![]);
+    }
+    let tip = repo.pick(&OPTS.rev(overlay))?;
+
+    let mut walk = git.revwalk()?;
+    walk.push(tip.id())?;
+    walk.simplify_first_parent()?;
+    let mut commits = walk.collect::<Result<Vec<_>, _>>()?;
+    commits.reverse();
+    log::info!("Tracing {} commits of overlay {}", commits.len(), overlay);
+
+    let mut present = HashMap::<String, HashSet<DepInfo>>::new();
+    let mut intervals = HashMap::<(String, DepInfo), Vec<Interval>>::new();
+    let mut prev = None;
+    for oid in commits {
+        let commit = git.find_commit(oid)?;
+        let tree = commit.tree()?;
+        let diff = git
+            .diff_tree_to_tree(prev.as_ref(), Some(&tree), None)
+            .context(format!("Diff {}", oid))?;
+        let mut dirs = HashSet::new();
+        for delta in diff.deltas() {
+            for file in &[delta.old_file(), delta.new_file()] {
+                if let Some(dir) = file.path().and_then(Path::to_str).and_then(package_dir) {
+                    dirs.insert(dir.to_string());
+                }
+            }
+        }
+
+        let event = Event {
+            commit: oid.to_string(),
+            time: commit.time().seconds(),
+        };
+        for dir in dirs {
+            let now = vulnerable_crates(git, &tree, overlay, &dir, advisories_of)?;
+            let before = present.remove(&dir).unwrap_or_default();
+            for dep in now.difference(&before) {
+                intervals
+                    .entry((dir.clone(), dep.clone()))
+                    .or_insert_with(Vec::new)
+                    .push(Interval {
+                        introduced: event.clone(),
+                        removed: None,
+                    });
+            }
+            for dep in before.difference(&now) {
+                if let Some(last) = intervals
+                    .get_mut(&(dir.clone(), dep.clone()))
+                    .and_then(|i| i.last_mut())
+                {
+                    last.removed = Some(event.clone());
+                }
+            }
+            if !now.is_empty() {
+                present.insert(dir, now);
+            }
+        }
+        prev = Some(tree);
+    }
+
+    let tip_time = tip.time().seconds();
+    let tip_tree = tip.tree()?;
+    let mut maintainers = HashMap::new();
+    Ok(intervals
+        .into_iter()
+        .map(|((package, id), intervals)| {
+            let advisories = advisories_of(&id);
+            let published = advisories
+                .iter()
+                .filter_map(|a| published_at(&a.date))
+                .min();
+            let last = intervals
+                .last()
+                .expect("Entries have at least one interval");
+            let exposed = published.map(|published| exposed_days(&intervals, published, tip_time));
+            let (days_to_fix, days_open) = match last.removed {
+                Some(_) => (exposed, None),
+                None => (None, exposed),
+            };
+            let maintainers = maintainers
+                .entry(package.clone())
+                .or_insert_with(|| package_maintainers(git, &tip_tree, overlay, &package))
+                .clone();
+            Exposure {
+                overlay: overlay.to_string(),
+                package,
+                id,
+                advisories,
+                maintainers,
+                intervals,
+                days_to_fix,
+                days_open,
+            }
+        })
+        .collect())
+}
+
+fn package_dir(path: &str) -> Option<&str> {
+    match path.ends_with(".ebuild") {
+        true => path.rfind('/').map(|i| &path[..i]),
+        false => None,
+    }
+}
+
+fn vulnerable_crates(
+    git: &git2::Repository,
+    tree: &git2::Tree,
+    overlay: &str,
+    dir: &str,
+    advisories_of: &impl Fn(&DepInfo) -> Vec<Published>,
+) -> Result<HashSet<DepInfo>> {
+    let mut ret = HashSet::new();
+    let entry = match tree.get_path(Path::new(dir)) {
+        Ok(entry) => entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(ret),
+        Err(e) => return Err(e.into()),
+    };
+    let object = entry.to_object(git)?;
+    let package = match object.as_tree() {
+        Some(package) => package,
+        None => return Ok(ret),
+    };
+    for entry in package.iter() {
+        let name = match entry.name() {
+            Some(name) if name.ends_with(".ebuild") => name,
+            _ => continue,
+        };
+        let content = entry.to_object(git)?;
+        let content = content.as_blob().context("Object blob")?.content();
+        let content = String::from_utf8_lossy(content);
+        if !uses_cargo(&content) {
+            continue;
+        }
+        let path = format!("{}/{}", dir, name);
+        if let Some(deps) = parse_crates(overlay, &path, &content) {
+            ret.extend(
+                deps.into_iter()
+                    .filter(|dep| !advisories_of(dep).is_empty()),
+            );
+        }
+    }
+    Ok(ret)
+}
+
+fn package_maintainers(
+    git: &git2::Repository,
+    tree: &git2::Tree,
+    overlay: &str,
+    package: &str,
+) -> Vec<String> {
+    let act = || -> Result<Vec<String>> {
+        let metadata = tree
+            .get_path(&Path::new(package).join("metadata.xml"))?
+            .to_object(git)?;
+        let metadata = metadata.as_blob().context("Object blob")?.content();
+        let metadata =
+            quick_xml::de::from_reader::<_, PkgMetadata>(std::io::Cursor::new(metadata))?;
+        Ok(metadata.maintainer.into_iter().map(|m| m.email).collect())
+    };
+    match act() {
+        Ok(maintainers) if !maintainers.is_empty() => maintainers,
+        Ok(_) => vec!["maintainer-needed".to_string()],
+        Err(e) => {
+            log::debug!(
+                "{}::{}: No maintainers:{}",
+                overlay,
+                package,
+                crate::format_chain(&e)
+            );
+            vec![]
+        }
+    }
+}
+
+fn published_at(date: &str) -> Option<i64> {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .map(|d| d.and_hms(0, 0, 0).timestamp())
+}
+
+fn days(seconds: i64) -> f64 {
+    seconds.max(0) as f64 / DAY
+}
+
+/// Days listed after publication over all intervals: a crate can be removed and come back, e.g.
+/// by a downgrade or in an older slot. Open intervals count up to tip_time.
+fn exposed_days(intervals: &[Interval], published: i64, tip_time: i64) -> f64 {
+    intervals
+        .iter()
+        .map(|i| {
+            let end = i.removed.as_ref().map_or(tip_time, |r| r.time);
+            days(end - published.max(i.introduced.time))
+        })
+        .sum()
+}
+
+fn stats(exposures: &[&Exposure]) -> Stats {
+    let mut ret = Stats::default();
+    let mut fix = vec![];
+    for e in exposures {
+        ret.exposures += 1;
+        match e.intervals.last().map(|i| i.removed.is_some()) {
+            Some(true) => ret.resolved += 1,
+            _ => ret.open += 1,
+        }
+        fix.extend(e.days_to_fix);
+    }
+    fix.sort_by(|a, b| a.partial_cmp(b).expect("No NaN days"));
+    if !fix.is_empty() {
+        ret.mean_days_to_fix = Some(fix.iter().sum::<f64>() / fix.len() as f64);
+        ret.median_days_to_fix = Some(fix[fix.len() / 2]);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exposure() {
+        let event = |day: i64| Event {
+            commit: String::new(),
+            time: day * DAY as i64,
+        };
+        let intervals = vec![
+            Interval {
+                introduced: event(0),
+                removed: Some(event(10)),
+            },
+            Interval {
+                introduced: event(20),
+                removed: Some(event(25)),
+            },
+            Interval {
+                introduced: event(30),
+                removed: None,
+            },
+        ];
+        // Published on day 5: 5 + 5 + 10 days
+        assert_eq!(
+            20.0,
+            exposed_days(&intervals, 5 * DAY as i64, 40 * DAY as i64)
+        );
+        // Published after the first removal
+        assert_eq!(5.0, exposed_days(&intervals[..2], 12 * DAY as i64, 0));
+    }
+
+    #[test]
+    fn dirs() {
+        assert_eq!(
+            Some("dev-vcs/gitui"),
+            package_dir("dev-vcs/gitui/gitui-0.12.0.ebuild")
+        );
+        assert_eq!(None, package_dir("dev-vcs/gitui/metadata.xml"));
+    }
+
+    #[test]
+    fn metadata() {
+        let metadata = quick_xml::de::from_reader::<_, PkgMetadata>(std::io::Cursor::new(
+            include_bytes!("tests/metadata.xml"),
+        ))
+        .unwrap();
+        assert_eq!(
+            vec!["ceba@liftm.de", "rust@gentoo.org"],
+            metadata
+                .maintainer
+                .iter()
+                .map(|m| m.email.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
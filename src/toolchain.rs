@@ -0,0 +1,73 @@
+//! Advisories against the Rust toolchain and standard library, which affect everything built with
+//! an old dev-lang/rust or dev-lang/rust-bin.
+
+use crate::{advisories, re, AdvisoryMeta, Ebuild};
+use rustsec::package::Version;
+use std::str::FromStr;
+
+const PACKAGES: &[&str] = &["dev-lang/rust", "dev-lang/rust-bin"];
+
+pub type Toolchains = dashmap::DashMap<Ebuild, Version>;
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ToolchainStatus {
+    pub ebuild: Ebuild,
+    pub version: Version,
+    pub advisories: Vec<AdvisoryMeta>,
+}
+
+/// The toolchain version an ebuild installs, if it is one of the toolchain packages.
+/// Live ebuilds have no usable version.
+pub fn version(path: &str) -> Option<Version> {
+    let package = &path[..path.rfind('/')?];
+    if !PACKAGES.contains(&package) {
+        return None;
+    }
+    let (_, ver) = re::split_pkgver(path)?;
+    Version::from_str(ver).ok()
+}
+
+/// Toolchain ebuilds with advisories, ordered by ebuild
+pub fn audit(toolchains: Toolchains, sec_db: &advisories::Advisories) -> Vec<ToolchainStatus> {
+    let mut ret = toolchains
+        .into_iter()
+        .filter_map(|(ebuild, version)| {
+            let advisories = sec_db
+                .toolchain(&version)
+                .into_iter()
+                .map(|f| f.meta())
+                .collect::<Vec<_>>();
+            if advisories.is_empty() {
+                return None;
+            }
+            Some(ToolchainStatus {
+                ebuild,
+                version,
+                advisories,
+            })
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|a, b| {
+        (&a.ebuild.overlay, &a.ebuild.path).cmp(&(&b.ebuild.overlay, &b.ebuild.path))
+    });
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(
+            Some(Version::from_str("1.51.0").unwrap()),
+            version("dev-lang/rust/rust-1.51.0-r2.ebuild")
+        );
+        assert_eq!(
+            Some(Version::from_str("1.52.1").unwrap()),
+            version("dev-lang/rust-bin/rust-bin-1.52.1.ebuild")
+        );
+        assert_eq!(None, version("dev-lang/rust/rust-9999.ebuild"));
+        assert_eq!(None, version("dev-util/rustup/rustup-1.24.2.ebuild"));
+    }
+}
@@ -0,0 +1,117 @@
+//! The closest versions that get a flagged crate out of trouble: not yanked and not affected by
+//! any vulnerability. Informational notices don't count, an unmaintained crate stays so in every
+//! version.
+
+use crate::{advisories, DepInfo};
+use rustsec::package::Version;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Upgrade {
+    /// Lowest safe version that is semver-compatible, i.e. a drop-in bump
+    pub compatible: Option<Version>,
+    /// Lowest safe version, possibly breaking
+    pub overall: Option<Version>,
+}
+
+/// versions: all versions of the crate in the index, with their yanked flag
+pub fn find(
+    dep: &DepInfo,
+    versions: &HashMap<Version, bool>,
+    sec_db: &advisories::Advisories,
+) -> Upgrade {
+    let mut candidates = versions
+        .iter()
+        .filter(|(v, yanked)| !**yanked && *v > &dep.ver && v.pre.is_empty())
+        .map(|(v, _)| v)
+        .collect::<Vec<_>>();
+    candidates.sort();
+    // Lowest first, so usually only a few need a query. Compatible versions form a contiguous
+    // range right above the current one, so if the lowest safe version isn't in it, none is.
+    let overall = candidates
+        .into_iter()
+        .find(|v| {
+            let dep = DepInfo {
+                name: dep.name.clone(),
+                ver: (*v).clone(),
+            };
+            !sec_db.query(&dep).iter().any(|f| f.is_vulnerability())
+        })
+        .cloned();
+    let compatible = overall.clone().filter(|v| semver_compatible(&dep.ver, v));
+    Upgrade {
+        compatible,
+        overall,
+    }
+}
+
+/// Whether cargo would pick b for a requirement of ^a
+fn semver_compatible(a: &Version, b: &Version) -> bool {
+    match (a.major, a.minor) {
+        (0, 0) => b.major == 0 && b.minor == 0 && b.patch == a.patch,
+        (0, _) => b.major == 0 && b.minor == a.minor,
+        _ => b.major == a.major,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::dep;
+    use std::str::FromStr;
+
+    #[test]
+    fn compatibility() {
+        let v = |s: &str| Version::from_str(s).unwrap();
+        assert!(semver_compatible(&v("1.2.3"), &v("1.9.0")));
+        assert!(!semver_compatible(&v("1.2.3"), &v("2.0.0")));
+        assert!(semver_compatible(&v("0.4.1"), &v("0.4.19")));
+        assert!(!semver_compatible(&v("0.4.1"), &v("0.5.0")));
+        assert!(!semver_compatible(&v("0.0.1"), &v("0.0.2")));
+    }
+
+    #[test]
+    fn upgrades() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/advisory-db");
+        let sec_db = advisories::Advisories::new(rustsec::database::Database::open(&path).unwrap());
+        let upgrade_of = |name: &str, ver: &str, versions: &[(&str, bool)]| {
+            let versions = versions
+                .iter()
+                .map(|(v, yanked)| (Version::from_str(v).unwrap(), *yanked))
+                .collect();
+            find(&dep(name, ver), &versions, &sec_db)
+        };
+        let upgrade = |compatible: Option<&str>, overall: Option<&str>| Upgrade {
+            compatible: compatible.map(|v| Version::from_str(v).unwrap()),
+            overall: overall.map(|v| Version::from_str(v).unwrap()),
+        };
+        assert_eq!(
+            upgrade(Some("1.2.0"), Some("1.2.0")),
+            upgrade_of(
+                "foo",
+                "1.0.0",
+                &[("1.1.0", false), ("1.2.0", false), ("2.0.0", false)]
+            )
+        );
+        // Nothing fixed yet
+        assert_eq!(
+            upgrade(None, None),
+            upgrade_of("foo", "1.0.0", &[("1.0.0", false), ("1.1.0", false)])
+        );
+        // Only fixed in a breaking release, whose first version is yanked. The unmaintained notice
+        // against every version doesn't count.
+        assert_eq!(
+            upgrade(None, Some("0.4.1")),
+            upgrade_of(
+                "qux",
+                "0.3.0",
+                &[("0.3.1", false), ("0.4.0", true), ("0.4.1", false)]
+            )
+        );
+        // Nothing but the notice left
+        assert_eq!(
+            upgrade(Some("0.4.1"), Some("0.4.1")),
+            upgrade_of("qux", "0.4.0", &[("0.4.1", false)])
+        );
+    }
+}